@@ -0,0 +1,76 @@
+// Compares neighbor queries through the SpatialGrid against scanning every particle
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rust_webgl_particles_backend::spatial_grid::SpatialGrid;
+use common::random_positions;
+
+const NUM_PARTICLES: usize = 20_000;
+const RADIUS: f64 = 10.0;
+
+fn naive_query(positions: &[[f64; 2]], center: [f64; 2], radius: f64) -> usize {
+    positions
+        .iter()
+        .filter(|pos| vecmath::vec2_square_len(vecmath::vec2_sub(**pos, center)) <= radius * radius)
+        .count()
+}
+
+fn bench_point_queries(c: &mut Criterion) {
+    let positions = random_positions(0, NUM_PARTICLES, [0.0, 0.0], [1600.0, 1200.0]);
+    let mut grid = SpatialGrid::new(RADIUS);
+    grid.rebuild(positions.iter().copied());
+    let centers = &positions[..100];
+
+    c.bench_function("grid rebuild", |b| {
+        b.iter(|| grid.rebuild(black_box(positions.iter().copied())))
+    });
+    c.bench_function("grid 100 point queries", |b| {
+        b.iter(|| {
+            centers
+                .iter()
+                .map(|center| grid.query_radius(*center, RADIUS).len())
+                .sum::<usize>()
+        })
+    });
+    c.bench_function("naive 100 point queries", |b| {
+        b.iter(|| {
+            centers
+                .iter()
+                .map(|center| naive_query(black_box(&positions), *center, RADIUS))
+                .sum::<usize>()
+        })
+    });
+}
+
+fn bench_pairs(c: &mut Criterion) {
+    let positions = random_positions(0, NUM_PARTICLES / 4, [0.0, 0.0], [1600.0, 1200.0]);
+    let mut grid = SpatialGrid::new(RADIUS);
+    grid.rebuild(positions.iter().copied());
+
+    c.bench_function("grid all pairs", |b| {
+        b.iter(|| {
+            let mut count = 0;
+            grid.for_each_pair_within(RADIUS, |_, _| count += 1);
+            count
+        })
+    });
+    c.bench_function("naive all pairs", |b| {
+        b.iter(|| {
+            let mut count = 0;
+            for i in 0..positions.len() {
+                for j in i + 1..positions.len() {
+                    let diff = vecmath::vec2_sub(positions[i], positions[j]);
+                    if vecmath::vec2_square_len(diff) <= RADIUS * RADIUS {
+                        count += 1;
+                    }
+                }
+            }
+            count
+        })
+    });
+}
+
+criterion_group!(benches, bench_point_queries, bench_pairs);
+criterion_main!(benches);
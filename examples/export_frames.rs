@@ -0,0 +1,77 @@
+// Renders a scene to a sequence of PNG frames, e.g. to turn into a video with
+//   ffmpeg -framerate 60 -i frames/frame_%05d.png -pix_fmt yuv420p particles.mp4
+//
+// cargo run --release --example export_frames -- [--scene scene.json] [--out frames]
+//     [--width 1280] [--height 720] [--frames 60] [--supersampling 2] [--fps 60]
+//     [--texture www/assets/spiral.png]
+//
+// Without a scene this starts from the same scene as the web page.
+
+use rust_webgl_particles_backend::frame_export::{self, FrameExportOptions};
+use rust_webgl_particles_backend::scene::Scene;
+use rust_webgl_particles_backend::simulation::Simulation;
+use std::error::Error;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let mut options = FrameExportOptions::default();
+    let mut scene_path = None;
+    let mut out_dir = PathBuf::from("frames");
+    let mut texture_path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/www/assets/spiral.png"));
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| format!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--scene" => scene_path = Some(PathBuf::from(value)),
+            "--out" => out_dir = PathBuf::from(value),
+            "--texture" => texture_path = PathBuf::from(value),
+            "--width" => options.width = parse(&flag, &value)?,
+            "--height" => options.height = parse(&flag, &value)?,
+            "--frames" => options.frame_count = parse(&flag, &value)?,
+            "--supersampling" => options.supersampling = parse(&flag, &value)?,
+            "--fps" => options.frame_dt = 1.0 / parse::<f64>(&flag, &value)?,
+            _ => return Err(format!("unknown option {}", flag).into()),
+        }
+    }
+
+    let mut sim = match scene_path {
+        Some(path) => {
+            let scene = Scene::from_json(&std::fs::read_to_string(path)?)?;
+            options.trail_scale = scene.view.particle_trail_scale;
+            let mut sim = Simulation::with_seed(scene.width, scene.height, scene.seed);
+            sim.load_scene(scene);
+            sim
+        }
+        None => {
+            let mut sim = Simulation::new(1280, 720);
+            sim.spawn_gravity_well(640.0, 360.0);
+            sim.initialize_particles(3000);
+            sim
+        }
+    };
+
+    let texture = frame_export::load_png_texture(&texture_path)?;
+    let paths = frame_export::export_frames(
+        &mut sim,
+        options,
+        vec![("gravity_well".to_owned(), texture)],
+        &out_dir,
+    )?;
+    println!("Wrote {} frames to {}", paths.len(), out_dir.display());
+    Ok(())
+}
+
+fn parse<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {} for {}", value, flag))
+}
@@ -0,0 +1,185 @@
+// Particle-particle (N-body) attraction approximated with a Barnes-Hut quadtree.
+// Distant groups of particles are treated as a single body at their center of mass,
+// theta controls how far away (relative to its size) a group must be for that,
+// trading accuracy for speed. A theta of 0 visits every particle.
+
+use serde::{Deserialize, Serialize};
+
+// Stops subdividing when many particles sit on the same spot,
+// they are lumped together in one leaf instead
+const MAX_DEPTH: u32 = 24;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NBodyConfig {
+    pub enabled: bool,
+    pub theta: f64,
+    // Gravitational constant, scales how strongly particles pull on each other
+    pub strength: f64,
+    pub particle_mass: f64,
+    // Plummer softening length in pixels, keeps close encounters from blowing up
+    pub softening: f64,
+}
+
+impl Default for NBodyConfig {
+    fn default() -> Self {
+        NBodyConfig {
+            enabled: false,
+            theta: 0.5,
+            strength: 1000.0,
+            particle_mass: 1.0,
+            softening: 5.0,
+        }
+    }
+}
+
+struct Node {
+    min: [f64; 2],
+    size: f64,
+    mass: f64,
+    // Sum of position * mass, divide by mass for the center of mass
+    weighted_pos: [f64; 2],
+    // Index of the first of the four children, zero for leaves
+    first_child: usize,
+    // Position of the particle stored in a leaf
+    body: Option<[f64; 2]>,
+}
+
+impl Node {
+    fn new(min: [f64; 2], size: f64) -> Self {
+        Node {
+            min,
+            size,
+            mass: 0.0,
+            weighted_pos: [0.0, 0.0],
+            first_child: 0,
+            body: None,
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.first_child == 0
+    }
+
+    fn center_of_mass(&self) -> [f64; 2] {
+        vecmath::vec2_scale(self.weighted_pos, 1.0 / self.mass)
+    }
+
+    fn add_mass(&mut self, pos: [f64; 2], mass: f64) {
+        self.mass += mass;
+        self.weighted_pos = vecmath::vec2_add(self.weighted_pos, vecmath::vec2_scale(pos, mass));
+    }
+
+    // Which of the four children pos falls into
+    fn quadrant(&self, pos: [f64; 2]) -> usize {
+        let half = self.size / 2.0;
+        let right = (pos[0] >= self.min[0] + half) as usize;
+        let bottom = (pos[1] >= self.min[1] + half) as usize;
+        right + bottom * 2
+    }
+}
+
+pub struct QuadTree {
+    nodes: Vec<Node>,
+}
+
+impl QuadTree {
+    // Builds a tree containing every position, each with the same mass
+    pub fn build<I>(positions: I, mass: f64) -> Self
+    where
+        I: Iterator<Item = [f64; 2]> + Clone,
+    {
+        let mut min = [f64::INFINITY, f64::INFINITY];
+        let mut max = [f64::NEG_INFINITY, f64::NEG_INFINITY];
+        for pos in positions.clone() {
+            min = [min[0].min(pos[0]), min[1].min(pos[1])];
+            max = [max[0].max(pos[0]), max[1].max(pos[1])];
+        }
+        // Square root node, slightly padded so the max corner lands inside it
+        let size = f64::max(max[0] - min[0], max[1] - min[1]).max(1.0) * 1.001;
+
+        let mut tree = QuadTree {
+            nodes: vec![Node::new(min, size)],
+        };
+        if min[0].is_finite() {
+            for pos in positions {
+                tree.insert(pos, mass);
+            }
+        }
+        tree
+    }
+
+    fn insert(&mut self, pos: [f64; 2], mass: f64) {
+        let mut index = 0;
+        let mut depth = 0;
+        loop {
+            let node = &mut self.nodes[index];
+            if node.is_leaf() {
+                match node.body {
+                    None if node.mass == 0.0 => {
+                        node.body = Some(pos);
+                        node.add_mass(pos, mass);
+                        return;
+                    }
+                    Some(existing) if depth < MAX_DEPTH => {
+                        // Split the leaf and push its particle down a level
+                        let existing_mass = node.mass;
+                        node.body = None;
+                        self.subdivide(index);
+                        let child = self.nodes[index].first_child
+                            + self.nodes[index].quadrant(existing);
+                        self.nodes[child].body = Some(existing);
+                        self.nodes[child].add_mass(existing, existing_mass);
+                    }
+                    _ => {
+                        // Too deep to split any further, lump it in with the rest
+                        node.add_mass(pos, mass);
+                        return;
+                    }
+                }
+            }
+            let node = &mut self.nodes[index];
+            node.add_mass(pos, mass);
+            index = node.first_child + node.quadrant(pos);
+            depth += 1;
+        }
+    }
+
+    fn subdivide(&mut self, index: usize) {
+        let first_child = self.nodes.len();
+        let min = self.nodes[index].min;
+        let half = self.nodes[index].size / 2.0;
+        for quadrant in 0..4 {
+            let child_min = [
+                min[0] + half * (quadrant % 2) as f64,
+                min[1] + half * (quadrant / 2) as f64,
+            ];
+            self.nodes.push(Node::new(child_min, half));
+        }
+        self.nodes[index].first_child = first_child;
+    }
+
+    // Approximate acceleration at pos due to every particle in the tree
+    pub fn acceleration(&self, pos: [f64; 2], config: &NBodyConfig) -> [f64; 2] {
+        let softening_squared = config.softening * config.softening;
+        let mut total = [0.0, 0.0];
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.mass == 0.0 {
+                continue;
+            }
+            let diff = vecmath::vec2_sub(node.center_of_mass(), pos);
+            let distance_squared = vecmath::vec2_square_len(diff);
+            if node.is_leaf() || node.size * node.size < config.theta * config.theta * distance_squared {
+                let softened = distance_squared + softening_squared;
+                if softened > 0.0 {
+                    let scale = config.strength * node.mass / (softened * softened.sqrt());
+                    total = vecmath::vec2_add(total, vecmath::vec2_scale(diff, scale));
+                }
+            } else {
+                stack.extend(node.first_child..node.first_child + 4);
+            }
+        }
+        total
+    }
+}
@@ -0,0 +1,71 @@
+// Decides what happens to particles that reach the edge of the simulation area
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BorderMode {
+    // Particles fly off freely and keep being simulated
+    Open = 0,
+    // Particles bounce off the edges, losing speed according to the restitution
+    Bounce = 1,
+    // Particles leaving one edge come back in on the opposite edge
+    Wrap = 2,
+    // Particles are removed as soon as they leave
+    Despawn = 3,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Borders {
+    pub mode: BorderMode,
+    // Fraction of speed kept when bouncing, 1.0 is perfectly elastic
+    // and 0.0 absorbs all speed perpendicular to the edge
+    pub restitution: f64,
+}
+
+impl Default for Borders {
+    fn default() -> Self {
+        Borders {
+            mode: BorderMode::Open,
+            restitution: 1.0,
+        }
+    }
+}
+
+impl Borders {
+    // Applies the border mode to a particle in a width x height area,
+    // returns false if the particle should be removed
+    pub fn apply(&self, pos: &mut [f64; 2], vel: &mut [f64; 2], width: f64, height: f64) -> bool {
+        let size = [width, height];
+        match self.mode {
+            BorderMode::Open => true,
+            BorderMode::Bounce => {
+                for axis in 0..2 {
+                    // Always sent back inwards, a particle that is already heading back
+                    // in (say after the area shrank) would otherwise get stuck to the edge
+                    if pos[axis] < 0.0 {
+                        vel[axis] = vel[axis].abs() * self.restitution;
+                    } else if pos[axis] >= size[axis] {
+                        vel[axis] = -vel[axis].abs() * self.restitution;
+                    } else {
+                        continue;
+                    }
+                    pos[axis] = pos[axis].max(0.0).min(f64::max(size[axis] - 1.0, 0.0));
+                }
+                true
+            }
+            BorderMode::Wrap => {
+                for axis in 0..2 {
+                    if size[axis] > 0.0 {
+                        pos[axis] = pos[axis].rem_euclid(size[axis]);
+                    }
+                }
+                true
+            }
+            BorderMode::Despawn => {
+                pos[0] >= 0.0 && pos[0] < width && pos[1] >= 0.0 && pos[1] < height
+            }
+        }
+    }
+}
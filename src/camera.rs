@@ -0,0 +1,77 @@
+// Camera looking at the world from above, the world point at its center is drawn
+// in the middle of the view and zoom is how many screen pixels one world unit takes up.
+// The view size is passed in where needed, since the canvas can be resized independently.
+
+use serde::{Deserialize, Serialize};
+extern crate nalgebra_glm as glm;
+use glm::TMat4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Camera {
+    pub center: [f64; 2],
+    pub zoom: f64,
+}
+
+impl Camera {
+    pub const MIN_ZOOM: f64 = 0.05;
+    pub const MAX_ZOOM: f64 = 50.0;
+
+    pub fn new(center: [f64; 2], zoom: f64) -> Self {
+        Camera {
+            center,
+            zoom: zoom.clamp(Camera::MIN_ZOOM, Camera::MAX_ZOOM),
+        }
+    }
+
+    // Camera showing a width by height view at one world unit per pixel,
+    // with the world origin in the top left corner
+    pub fn fitting(width: f64, height: f64) -> Self {
+        Camera::new([width / 2.0, height / 2.0], 1.0)
+    }
+
+    pub fn screen_to_world(&self, screen: [f64; 2], view_size: [f64; 2]) -> [f64; 2] {
+        [
+            self.center[0] + (screen[0] - view_size[0] / 2.0) / self.zoom,
+            self.center[1] + (screen[1] - view_size[1] / 2.0) / self.zoom,
+        ]
+    }
+
+    pub fn world_to_screen(&self, world: [f64; 2], view_size: [f64; 2]) -> [f64; 2] {
+        [
+            (world[0] - self.center[0]) * self.zoom + view_size[0] / 2.0,
+            (world[1] - self.center[1]) * self.zoom + view_size[1] / 2.0,
+        ]
+    }
+
+    // Moves the view by a distance in screen pixels, the world moves the opposite way
+    pub fn pan_by(&mut self, screen_dx: f64, screen_dy: f64) {
+        self.center[0] -= screen_dx / self.zoom;
+        self.center[1] -= screen_dy / self.zoom;
+    }
+
+    // Multiplies the zoom by factor, keeping the world point under the screen point in place
+    pub fn zoom_at(&mut self, screen: [f64; 2], factor: f64, view_size: [f64; 2]) {
+        if !(factor.is_finite() && factor > 0.0) {
+            return;
+        }
+        let anchor = self.screen_to_world(screen, view_size);
+        self.zoom = (self.zoom * factor).clamp(Camera::MIN_ZOOM, Camera::MAX_ZOOM);
+        let moved = self.world_to_screen(anchor, view_size);
+        self.pan_by(screen[0] - moved[0], screen[1] - moved[1]);
+    }
+
+    // Orthographic projection from world coordinates into normalized device coordinates,
+    // with y pointing down like the screen
+    pub fn projection(&self, view_size: [f64; 2]) -> TMat4<f32> {
+        let half_width = view_size[0] / 2.0 / self.zoom;
+        let half_height = view_size[1] / 2.0 / self.zoom;
+        glm::ortho(
+            (self.center[0] - half_width) as f32,
+            (self.center[0] + half_width) as f32,
+            (self.center[1] + half_height) as f32,
+            (self.center[1] - half_height) as f32,
+            1.0,
+            -1.0,
+        )
+    }
+}
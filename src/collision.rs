@@ -0,0 +1,95 @@
+// Particle-particle interactions that only involve close neighbors:
+// elastic collisions between particles with a radius, and a softer
+// separation force that keeps particles from bunching up.
+// Both look up neighbors through a SpatialGrid built from the particle positions.
+
+use crate::particle::Particles;
+use crate::spatial_grid::SpatialGrid;
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CollisionConfig {
+    pub collisions_enabled: bool,
+    // Particles are treated as discs with this radius when colliding
+    pub radius: f64,
+    // Acceleration pushing apart particles closer than separation_radius,
+    // fades linearly to zero at the edge of the radius. Zero disables it
+    pub separation_strength: f64,
+    pub separation_radius: f64,
+}
+
+impl Default for CollisionConfig {
+    fn default() -> Self {
+        CollisionConfig {
+            collisions_enabled: false,
+            radius: 2.0,
+            separation_strength: 0.0,
+            separation_radius: 10.0,
+        }
+    }
+}
+
+impl CollisionConfig {
+    pub fn is_active(&self) -> bool {
+        self.collisions_enabled || self.separation_strength != 0.0
+    }
+
+    // Grid cell size big enough for every neighbor query this config needs
+    pub fn cell_size(&self) -> f64 {
+        f64::max(self.radius * 2.0, self.separation_radius).max(1.0)
+    }
+}
+
+// Bounces overlapping particles off each other, as equal mass elastic discs.
+// grid must have been built from the current particle positions
+pub fn resolve_collisions(particles: &mut Particles, grid: &SpatialGrid, config: &CollisionConfig) {
+    let diameter = config.radius * 2.0;
+    grid.for_each_pair_within(diameter, |i, j| {
+        let diff = vecmath::vec2_sub(particles.pos(j), particles.pos(i));
+        let distance = vecmath::vec2_len(diff);
+        if distance == 0.0 {
+            return;
+        }
+        // The grid was built before any pushes, an earlier pair may have
+        // already moved these two out of contact
+        if distance >= diameter {
+            return;
+        }
+        let normal = vecmath::vec2_scale(diff, 1.0 / distance);
+
+        // Push the pair apart so they just touch
+        let correction = vecmath::vec2_scale(normal, (diameter - distance) / 2.0);
+        particles.set_pos(i, vecmath::vec2_sub(particles.pos(i), correction));
+        particles.set_pos(j, vecmath::vec2_add(particles.pos(j), correction));
+
+        // Swap the velocity components along the normal if they are approaching
+        let relative_vel = vecmath::vec2_sub(particles.vel(j), particles.vel(i));
+        let approach_speed = vecmath::vec2_dot(relative_vel, normal);
+        if approach_speed < 0.0 {
+            let impulse = vecmath::vec2_scale(normal, approach_speed);
+            particles.set_vel(i, vecmath::vec2_add(particles.vel(i), impulse));
+            particles.set_vel(j, vecmath::vec2_sub(particles.vel(j), impulse));
+        }
+    });
+}
+
+// Nudges the velocities of nearby particles apart for dt seconds.
+// grid must have been built from the current particle positions
+pub fn apply_separation(
+    particles: &mut Particles,
+    grid: &SpatialGrid,
+    config: &CollisionConfig,
+    dt: f64,
+) {
+    grid.for_each_pair_within(config.separation_radius, |i, j| {
+        let diff = vecmath::vec2_sub(particles.pos(j), particles.pos(i));
+        let distance = vecmath::vec2_len(diff);
+        if distance == 0.0 {
+            return;
+        }
+        let falloff = 1.0 - distance / config.separation_radius;
+        let push = vecmath::vec2_scale(diff, config.separation_strength * falloff * dt / distance);
+        particles.set_vel(i, vecmath::vec2_sub(particles.vel(i), push));
+        particles.set_vel(j, vecmath::vec2_add(particles.vel(j), push));
+    });
+}
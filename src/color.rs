@@ -1,5 +1,9 @@
 // Simple color struct, created from an unsigned 32 representing RRGGBBAA
-#[derive(Copy, Clone)]
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -12,11 +16,25 @@ impl Color {
         let r = (num >> 24) as u8;
         let g = (num >> 16) as u8;
         let b = (num >> 8) as u8;
-        let a = (num >> 0) as u8;
+        let a = num as u8;
 
         Color { r, g, b, a }
     }
 
+    pub fn to_u32(self) -> u32 {
+        (self.r as u32) << 24 | (self.g as u32) << 16 | (self.b as u32) << 8 | self.a as u32
+    }
+
+    // Fully opaque color with random red, green and blue
+    pub fn random<R: Rng>(rng: &mut R) -> Color {
+        Color {
+            r: rng.gen::<u8>(),
+            g: rng.gen::<u8>(),
+            b: rng.gen::<u8>(),
+            a: 0xff,
+        }
+    }
+
     pub fn tint(&mut self, tint: Color) {
         let new_r = ((self.r as f64 + tint.r as f64) / 2.0) as u8;
         let new_g = ((self.g as f64 + tint.g as f64) / 2.0) as u8;
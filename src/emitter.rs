@@ -0,0 +1,137 @@
+// Emitters continuously spawn particles from a point, line, circle or rectangle
+// at a given rate, with a configurable initial velocity, palette and lifetime
+
+use crate::color::Color;
+use crate::particle::Particle;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EmitterShape {
+    // Spawns everything at pos
+    Point = 0,
+    // Spawns along the segment from pos to pos + size
+    Line = 1,
+    // Spawns inside a circle around pos with radius size[0]
+    Circle = 2,
+    // Spawns inside a size[0] x size[1] rectangle centered on pos
+    Rectangle = 3,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Emitter {
+    pub id: u32,
+    pub shape: EmitterShape,
+    pub pos: [f64; 2],
+    pub size: [f64; 2],
+    // Particles spawned per second
+    pub rate: f64,
+    // Initial speed in pixels per second, randomized by up to +/- speed_spread
+    pub speed: f64,
+    pub speed_spread: f64,
+    // Direction particles are launched in, randomized by up to +/- angle_spread_deg
+    pub angle_deg: f64,
+    pub angle_spread_deg: f64,
+    // Colors to pick from, random colors are used if this is empty
+    pub palette: Vec<Color>,
+    // Lifetime in seconds given to spawned particles
+    #[serde(with = "crate::scene::infinite_as_null")]
+    pub lifetime: f64,
+    #[serde(skip)]
+    pub(crate) spawn_accumulator: f64,
+}
+
+impl Emitter {
+    // Particles per second, anything faster would swamp the simulation
+    pub const MAX_RATE: f64 = 1_000_000.0;
+
+    pub fn new(id: u32, shape: EmitterShape, pos: [f64; 2]) -> Self {
+        Emitter {
+            id,
+            shape,
+            pos,
+            size: [50.0, 50.0],
+            rate: 300.0,
+            speed: 100.0,
+            speed_spread: 50.0,
+            angle_deg: 0.0,
+            angle_spread_deg: 180.0,
+            palette: Vec::new(),
+            lifetime: 4.0,
+            spawn_accumulator: 0.0,
+        }
+    }
+
+    // Advances the emitter by dt seconds and returns how many particles
+    // it should spawn, fractional particles are carried over to the next call.
+    // Rates are capped at MAX_RATE, and one that isn't a finite number spawns nothing
+    pub fn advance(&mut self, dt: f64) -> u32 {
+        if self.rate.is_finite() {
+            self.spawn_accumulator += self.rate.clamp(0.0, Emitter::MAX_RATE) * dt;
+        }
+        if !self.spawn_accumulator.is_finite() {
+            self.spawn_accumulator = 0.0;
+            return 0;
+        }
+        let count = self.spawn_accumulator.floor();
+        self.spawn_accumulator -= count;
+        count as u32
+    }
+
+    // Name of the first field the simulation can't run with, if there is one
+    pub(crate) fn invalid_field(&self) -> Option<&'static str> {
+        let checks = [
+            ("pos", self.pos.iter().all(|v| v.is_finite())),
+            ("size", self.size.iter().all(|v| v.is_finite())),
+            ("rate", (0.0..=Emitter::MAX_RATE).contains(&self.rate)),
+            ("speed", self.speed.is_finite()),
+            ("speed_spread", self.speed_spread.is_finite()),
+            ("angle_deg", self.angle_deg.is_finite()),
+            ("angle_spread_deg", self.angle_spread_deg.is_finite()),
+            // Infinite for particles that never expire
+            ("lifetime", self.lifetime > 0.0),
+        ];
+        checks.iter().find(|(_, valid)| !valid).map(|(field, _)| *field)
+    }
+
+    // Creates a single particle somewhere on the emitter's shape
+    pub fn emit<R: Rng>(&self, rng: &mut R) -> Particle {
+        let pos = self.random_position(rng);
+
+        let speed = self.speed + self.speed_spread * rng.gen_range(-1.0, 1.0);
+        let angle = (self.angle_deg + self.angle_spread_deg * rng.gen_range(-1.0, 1.0)).to_radians();
+        let vel = [speed * angle.cos(), speed * angle.sin()];
+
+        let color = if self.palette.is_empty() {
+            Color::random(rng)
+        } else {
+            self.palette[rng.gen_range(0, self.palette.len())]
+        };
+
+        let mut particle = Particle::new(pos[0], pos[1], vel[0], vel[1], color);
+        particle.lifetime = self.lifetime;
+        particle
+    }
+
+    fn random_position<R: Rng>(&self, rng: &mut R) -> [f64; 2] {
+        match self.shape {
+            EmitterShape::Point => self.pos,
+            EmitterShape::Line => {
+                let t = rng.gen::<f64>();
+                [self.pos[0] + self.size[0] * t, self.pos[1] + self.size[1] * t]
+            }
+            EmitterShape::Circle => {
+                // sqrt keeps the points uniformly distributed over the area
+                let radius = self.size[0] * rng.gen::<f64>().sqrt();
+                let angle = rng.gen::<f64>() * std::f64::consts::PI * 2.0;
+                [self.pos[0] + radius * angle.cos(), self.pos[1] + radius * angle.sin()]
+            }
+            EmitterShape::Rectangle => [
+                self.pos[0] + self.size[0] * (rng.gen::<f64>() - 0.5),
+                self.pos[1] + self.size[1] * (rng.gen::<f64>() - 0.5),
+            ],
+        }
+    }
+}
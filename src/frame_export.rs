@@ -0,0 +1,266 @@
+// Native only export of rendered frames to numbered PNG files, for making videos offline.
+// Frames go through the same Renderer as the browser, on the software backend.
+// By default the simulation area is fit into the output resolution keeping its
+// aspect ratio. Frames can be rendered at a multiple of the resolution then scaled
+// down to smooth out edges.
+
+use crate::camera::Camera;
+use crate::renderer::Renderer;
+use crate::simulation::Simulation;
+use crate::software_backend::{SoftwareBackend, Texture};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+#[derive(Copy, Clone, Debug)]
+pub struct FrameExportOptions {
+    // Size of the output images in pixels
+    pub width: u32,
+    pub height: u32,
+    pub frame_count: u32,
+    // Frames are rendered at this many times the output resolution, then averaged down
+    pub supersampling: u32,
+    // Seconds simulated between frames, split into equal ticks no longer
+    // than the simulation's fixed timestep
+    pub frame_dt: f64,
+    pub trail_scale: f64,
+    // What part of the world to show, zoom is in output pixels per world unit.
+    // Without one the whole simulation area is shown
+    pub camera: Option<Camera>,
+}
+
+impl Default for FrameExportOptions {
+    fn default() -> Self {
+        FrameExportOptions {
+            width: 1280,
+            height: 720,
+            frame_count: 60,
+            supersampling: 2,
+            frame_dt: 1.0 / 60.0,
+            trail_scale: 0.1,
+            camera: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FrameExportError {
+    InvalidOptions(String),
+    Io(std::io::Error),
+    Encoding(png::EncodingError),
+    Decoding(png::DecodingError),
+}
+
+impl fmt::Display for FrameExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameExportError::InvalidOptions(reason) => write!(f, "Invalid export options: {}", reason),
+            FrameExportError::Io(err) => write!(f, "Failed to write frame: {}", err),
+            FrameExportError::Encoding(err) => write!(f, "Failed to encode frame: {}", err),
+            FrameExportError::Decoding(err) => write!(f, "Failed to decode texture: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for FrameExportError {}
+
+impl From<std::io::Error> for FrameExportError {
+    fn from(err: std::io::Error) -> Self {
+        FrameExportError::Io(err)
+    }
+}
+
+impl From<png::EncodingError> for FrameExportError {
+    fn from(err: png::EncodingError) -> Self {
+        FrameExportError::Encoding(err)
+    }
+}
+
+impl From<png::DecodingError> for FrameExportError {
+    fn from(err: png::DecodingError) -> Self {
+        FrameExportError::Decoding(err)
+    }
+}
+
+// Renders frames of a simulation to RGBA buffers at the export resolution
+pub struct FrameRenderer {
+    options: FrameExportOptions,
+    renderer: Renderer<SoftwareBackend>,
+}
+
+impl FrameRenderer {
+    pub fn new(options: FrameExportOptions) -> Result<Self, FrameExportError> {
+        if options.width == 0 || options.height == 0 {
+            return Err(FrameExportError::InvalidOptions(format!(
+                "resolution must not be zero, got {}x{}",
+                options.width, options.height
+            )));
+        }
+        if !(1..=8).contains(&options.supersampling) {
+            return Err(FrameExportError::InvalidOptions(format!(
+                "supersampling must be from 1 to 8, got {}",
+                options.supersampling
+            )));
+        }
+        if !(options.frame_dt >= 0.0 && options.frame_dt.is_finite()) {
+            return Err(FrameExportError::InvalidOptions(format!(
+                "frame_dt must not be negative, got {}",
+                options.frame_dt
+            )));
+        }
+        if let Some(camera) = options.camera {
+            if !(camera.zoom > 0.0 && camera.zoom.is_finite()) {
+                return Err(FrameExportError::InvalidOptions(format!(
+                    "camera zoom must be positive, got {}",
+                    camera.zoom
+                )));
+            }
+        }
+
+        let mut backend = SoftwareBackend::new(
+            options.width * options.supersampling,
+            options.height * options.supersampling,
+        );
+        // Keeps trails one output pixel wide, like they are in the browser
+        backend.set_line_width(options.supersampling as f32);
+        Ok(FrameRenderer {
+            options,
+            renderer: Renderer::new(backend),
+        })
+    }
+
+    pub fn add_texture(&mut self, name: &str, texture: Texture) {
+        self.renderer.backend.add_texture(name, texture);
+    }
+
+    // Renders the current state of sim, returning RGBA pixels at the output resolution
+    pub fn render(&mut self, sim: &Simulation) -> Vec<u8> {
+        let options = &self.options;
+        let mut camera = options.camera.unwrap_or_else(|| {
+            let (width, height) = (sim.width().max(1) as f64, sim.height().max(1) as f64);
+            let zoom = (options.width as f64 / width).min(options.height as f64 / height);
+            Camera {
+                center: [width / 2.0, height / 2.0],
+                zoom,
+            }
+        });
+        // Drawn at the supersampled resolution
+        camera.zoom *= options.supersampling as f64;
+
+        self.renderer.set_camera(&camera);
+        self.renderer.clear_screen();
+        self.renderer
+            .render_particles(sim.particles(), options.trail_scale);
+        self.renderer.render_gravity_wells(sim.gravity_wells());
+
+        downsample(
+            self.renderer.backend.pixels(),
+            options.width,
+            options.height,
+            options.supersampling,
+        )
+    }
+}
+
+// Steps sim forward and writes frame_00000.png, frame_00001.png, ... into directory,
+// creating it if needed. The first frame is the state sim starts in.
+// Returns the paths written
+pub fn export_frames(
+    sim: &mut Simulation,
+    options: FrameExportOptions,
+    textures: Vec<(String, Texture)>,
+    directory: &Path,
+) -> Result<Vec<PathBuf>, FrameExportError> {
+    let mut renderer = FrameRenderer::new(options)?;
+    if options.frame_dt <= 0.0 {
+        return Err(FrameExportError::InvalidOptions(format!(
+            "frame_dt must be positive, got {}",
+            options.frame_dt
+        )));
+    }
+    // Every frame runs the same ticks, shortened from the fixed timestep when it doesn't
+    // divide frame_dt so the video still plays at the right speed. The small allowance
+    // keeps rounding error from adding a tick when it does divide it. Ticking directly
+    // instead of going through step keeps the leftover time in sim's accumulator
+    // from shifting ticks from one frame to the next
+    let ticks_per_frame = (options.frame_dt / sim.get_fixed_timestep() - 1e-9).ceil().max(1.0);
+    let tick_dt = options.frame_dt / ticks_per_frame;
+    for (name, texture) in textures {
+        renderer.add_texture(&name, texture);
+    }
+    fs::create_dir_all(directory)?;
+
+    let mut paths = Vec::with_capacity(options.frame_count as usize);
+    for frame in 0..options.frame_count {
+        if frame > 0 {
+            for _ in 0..ticks_per_frame as u64 {
+                sim.tick(tick_dt);
+            }
+        }
+        let pixels = renderer.render(sim);
+        let path = directory.join(format!("frame_{:05}.png", frame));
+        write_png(&path, options.width, options.height, &pixels)?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+pub fn write_png(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<(), FrameExportError> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(pixels)?;
+    Ok(())
+}
+
+// Loads a PNG as a texture, e.g. www/assets/spiral.png for the gravity wells
+pub fn load_png_texture(path: &Path) -> Result<Texture, FrameExportError> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    buffer.truncate(info.buffer_size());
+
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => buffer,
+        png::ColorType::Rgb => buffer
+            .chunks_exact(3)
+            .flat_map(|p| vec![p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buffer
+            .chunks_exact(2)
+            .flat_map(|p| vec![p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => buffer.iter().flat_map(|&g| vec![g, g, g, 255]).collect(),
+        // Expanded to RGB or RGBA by the transformations above
+        png::ColorType::Indexed => unreachable!(),
+    };
+    Ok(Texture::new(info.width, info.height, pixels))
+}
+
+// Averages each factor by factor block of pixels down into one
+fn downsample(pixels: &[u8], width: u32, height: u32, factor: u32) -> Vec<u8> {
+    if factor == 1 {
+        return pixels.to_vec();
+    }
+    let source_width = (width * factor) as usize;
+    let samples = factor * factor;
+    let mut output = Vec::with_capacity(width as usize * height as usize * 4);
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            let mut sum = [0u32; 4];
+            for sample_y in y * factor as usize..(y + 1) * factor as usize {
+                for sample_x in x * factor as usize..(x + 1) * factor as usize {
+                    let i = (sample_y * source_width + sample_x) * 4;
+                    for (total, value) in sum.iter_mut().zip(&pixels[i..i + 4]) {
+                        *total += *value as u32;
+                    }
+                }
+            }
+            output.extend(sum.iter().map(|total| ((total + samples / 2) / samples) as u8));
+        }
+    }
+    output
+}
@@ -1,37 +1,62 @@
 // Simple gravity well class, keeps track of position, rotation,
 // and can determine if a click event lands within its borders
 
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+// Decides which way a well pushes particles: attractors pull them in,
+// repulsors push them away and vortices swirl them around the well
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WellKind {
+    Attractor = 0,
+    Repulsor = 1,
+    Vortex = 2,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct GravityWell {
     pub pos: [f64; 2],
     pub rotation_deg: f64,
     pub mass: f64,
+    pub kind: WellKind,
+    #[serde(skip)]
     pub is_selected: bool,
 }
 
 impl GravityWell {
     pub const RADIUS: u32 = 20;
-    pub const ROTATION_SPEED: f64 = 2.0;
+    // Degrees per second
+    pub const ROTATION_SPEED: f64 = 120.0;
+    pub const DEFAULT_MASS: f64 = 90.0;
 
-    pub fn new(pos: [f64; 2], mass: f64) -> Self {
+    pub fn new(pos: [f64; 2], mass: f64, kind: WellKind) -> Self {
         GravityWell {
             pos,
             rotation_deg: 0.0,
             mass,
+            kind,
             is_selected: false,
         }
     }
 
+    // Turns a force magnitude and the unit direction from a particle to this well
+    // into an acceleration, depending on the kind of well
+    pub fn acceleration(&self, dir_to_well: [f64; 2], force: f64) -> [f64; 2] {
+        match self.kind {
+            WellKind::Attractor => vecmath::vec2_scale(dir_to_well, force),
+            WellKind::Repulsor => vecmath::vec2_scale(dir_to_well, -force),
+            WellKind::Vortex => vecmath::vec2_scale([-dir_to_well[1], dir_to_well[0]], force),
+        }
+    }
+
     // Used for checking if the user's click is inside this gravity well
     // For clicking and dragging
-    pub fn is_point_inside(&self, x: i32, y: i32) -> bool {
-        let delta_x = (x as f64 - self.pos[0]).abs();
-        let delta_y = (y as f64 - self.pos[1]).abs();
+    pub fn is_point_inside(&self, x: f64, y: f64) -> bool {
+        let delta_x = (x - self.pos[0]).abs();
+        let delta_y = (y - self.pos[1]).abs();
         let distance_from_well = glm::length(&glm::vec2(delta_x, delta_y));
-        if distance_from_well <= GravityWell::RADIUS as f64 {
-            true
-        } else {
-            false
-        }
+        distance_from_well <= GravityWell::RADIUS as f64
     }
 
     // Get canvas coordinates of each side of the gravity well
@@ -0,0 +1,109 @@
+// Undo/redo history for edits the user makes to gravity wells.
+// Wells are identified by their index in the simulation's well list, which stays
+// consistent as long as every well edit is recorded here and undone in order.
+
+use crate::gravity_well::GravityWell;
+
+// Oldest edits are dropped once the history grows past this
+const MAX_EDITS: usize = 200;
+
+#[derive(Clone)]
+pub enum Edit {
+    AddWell { index: usize, well: GravityWell },
+    RemoveWell { index: usize, well: GravityWell },
+    MoveWell { index: usize, from: [f64; 2], to: [f64; 2] },
+    SetWellMass { index: usize, from: f64, to: f64 },
+}
+
+#[derive(Default)]
+pub struct History {
+    undo_stack: Vec<Edit>,
+    redo_stack: Vec<Edit>,
+    // While a drag is in progress every move of the same well
+    // is folded into the last entry instead of adding a new one
+    drag_in_progress: bool,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History::default()
+    }
+
+    pub fn record(&mut self, edit: Edit) {
+        self.redo_stack.clear();
+        if let Edit::MoveWell { index, to, .. } = edit {
+            if self.drag_in_progress {
+                if let Some(Edit::MoveWell {
+                    index: last_index,
+                    to: last_to,
+                    ..
+                }) = self.undo_stack.last_mut()
+                {
+                    if *last_index == index {
+                        *last_to = to;
+                        return;
+                    }
+                }
+            }
+            self.drag_in_progress = true;
+        } else {
+            self.drag_in_progress = false;
+        }
+
+        self.undo_stack.push(edit);
+        if self.undo_stack.len() > MAX_EDITS {
+            self.undo_stack.remove(0);
+        }
+    }
+
+    // Stops folding moves into the last entry, the next move starts a new one
+    pub fn end_drag(&mut self) {
+        self.drag_in_progress = false;
+    }
+
+    // Moves the most recent edit over to the redo stack and returns it so it can be reverted
+    pub fn undo(&mut self) -> Option<&Edit> {
+        self.drag_in_progress = false;
+        let edit = self.undo_stack.pop()?;
+        self.redo_stack.push(edit);
+        self.redo_stack.last()
+    }
+
+    // Moves the most recently undone edit back to the undo stack and returns it so it can be reapplied
+    pub fn redo(&mut self) -> Option<&Edit> {
+        self.drag_in_progress = false;
+        let edit = self.redo_stack.pop()?;
+        self.undo_stack.push(edit);
+        self.undo_stack.last()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    // Scales the well positions stored in every edit, for when everything
+    // in the simulation is scaled to a new size
+    pub fn scale_positions(&mut self, scale_x: f64, scale_y: f64) {
+        let scale = |pos: &mut [f64; 2]| *pos = [pos[0] * scale_x, pos[1] * scale_y];
+        for edit in self.undo_stack.iter_mut().chain(&mut self.redo_stack) {
+            match edit {
+                Edit::AddWell { well, .. } | Edit::RemoveWell { well, .. } => scale(&mut well.pos),
+                Edit::MoveWell { from, to, .. } => {
+                    scale(from);
+                    scale(to);
+                }
+                Edit::SetWellMass { .. } => {}
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.drag_in_progress = false;
+    }
+}
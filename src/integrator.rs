@@ -0,0 +1,69 @@
+// Numerical integration schemes for stepping a particle's position and velocity
+// forward in time, given a function that computes its acceleration
+// from a position and velocity
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Integrator {
+    SemiImplicitEuler = 0,
+    VelocityVerlet = 1,
+    Rk4 = 2,
+}
+
+impl Integrator {
+    // Advances pos and vel by dt seconds using this integration scheme
+    pub fn step<F>(self, pos: &mut [f64; 2], vel: &mut [f64; 2], dt: f64, acceleration: F)
+    where
+        F: Fn([f64; 2], [f64; 2]) -> [f64; 2],
+    {
+        match self {
+            Integrator::SemiImplicitEuler => {
+                let acc = acceleration(*pos, *vel);
+                *vel = vecmath::vec2_add(*vel, vecmath::vec2_scale(acc, dt));
+                *pos = vecmath::vec2_add(*pos, vecmath::vec2_scale(*vel, dt));
+            }
+            Integrator::VelocityVerlet => {
+                let acc = acceleration(*pos, *vel);
+                let half_vel = vecmath::vec2_add(*vel, vecmath::vec2_scale(acc, 0.5 * dt));
+                *pos = vecmath::vec2_add(*pos, vecmath::vec2_scale(half_vel, dt));
+                let new_acc = acceleration(*pos, half_vel);
+                *vel = vecmath::vec2_add(half_vel, vecmath::vec2_scale(new_acc, 0.5 * dt));
+            }
+            Integrator::Rk4 => {
+                let (p0, v0) = (*pos, *vel);
+                let k1_pos = v0;
+                let k1_vel = acceleration(p0, v0);
+
+                let p1 = vecmath::vec2_add(p0, vecmath::vec2_scale(k1_pos, 0.5 * dt));
+                let v1 = vecmath::vec2_add(v0, vecmath::vec2_scale(k1_vel, 0.5 * dt));
+                let k2_pos = v1;
+                let k2_vel = acceleration(p1, v1);
+
+                let p2 = vecmath::vec2_add(p0, vecmath::vec2_scale(k2_pos, 0.5 * dt));
+                let v2 = vecmath::vec2_add(v0, vecmath::vec2_scale(k2_vel, 0.5 * dt));
+                let k3_pos = v2;
+                let k3_vel = acceleration(p2, v2);
+
+                let p3 = vecmath::vec2_add(p0, vecmath::vec2_scale(k3_pos, dt));
+                let v3 = vecmath::vec2_add(v0, vecmath::vec2_scale(k3_vel, dt));
+                let k4_pos = v3;
+                let k4_vel = acceleration(p3, v3);
+
+                *pos = vecmath::vec2_add(p0, weighted_sum(k1_pos, k2_pos, k3_pos, k4_pos, dt));
+                *vel = vecmath::vec2_add(v0, weighted_sum(k1_vel, k2_vel, k3_vel, k4_vel, dt));
+            }
+        }
+    }
+}
+
+// Combines the four RK4 slopes as dt / 6 * (k1 + 2 * k2 + 2 * k3 + k4)
+fn weighted_sum(k1: [f64; 2], k2: [f64; 2], k3: [f64; 2], k4: [f64; 2], dt: f64) -> [f64; 2] {
+    let sum = vecmath::vec2_add(
+        vecmath::vec2_add(k1, vecmath::vec2_scale(k2, 2.0)),
+        vecmath::vec2_add(vecmath::vec2_scale(k3, 2.0), k4),
+    );
+    vecmath::vec2_scale(sum, dt / 6.0)
+}
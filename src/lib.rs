@@ -1,18 +1,47 @@
 extern crate libc;
 extern crate nalgebra_glm as glm;
-mod color;
-mod gravity_well;
-mod particle;
-mod renderer;
+pub mod barnes_hut;
+pub mod border;
+pub mod camera;
+pub mod collision;
+pub mod color;
+pub mod drag;
+pub mod emitter;
+pub mod force_law;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod frame_export;
+pub mod gravity_well;
+pub mod history;
+pub mod integrator;
+pub mod particle;
+pub mod recording;
+pub mod renderer;
+pub mod rng;
+pub mod scene;
+#[cfg(feature = "simd")]
+mod simd;
+pub mod simulation;
+pub mod snapshot;
+pub mod software_backend;
+pub mod spatial_grid;
+pub mod speed_limit;
 mod utils;
+mod webgl_backend;
 mod webgl_helpers;
+use border::BorderMode;
+use camera::Camera;
 use color::Color;
-use gravity_well::GravityWell;
-use particle::Particle;
-use rand::Rng;
+use drag::DragModel;
+use emitter::{Emitter, EmitterShape};
+use force_law::ForceLaw;
+use gravity_well::WellKind;
+use integrator::Integrator;
+use recording::{InputEvent, Recorder, Recording, Replay};
 use renderer::Renderer;
-use std::collections::VecDeque;
-use vecmath;
+use scene::{Scene, ViewSettings};
+use simulation::Simulation;
+use speed_limit::SpeedLimitMode;
+use webgl_backend::WebGlBackend;
 use wasm_bindgen::prelude::*;
 use web_sys::{ console, HtmlCanvasElement, HtmlImageElement, WebGlRenderingContext };
 
@@ -37,135 +66,160 @@ impl<'a> Drop for Timer<'a> {
     }
 }
 
-// Rust portion of the main app, owns all state for the particle simulation
-// and decides how to handle user input (sent from JavaScript front end)
+// Rust portion of the main app, a thin wrapper around the Simulation
+// that decides how to handle user input (sent from JavaScript front end)
 // Has a Renderer struct that handles rendering to the WebGl context
 // that it gets from the DOM
 #[wasm_bindgen]
 pub struct WasmApp {
-    width: u32,
-    height: u32,
-    renderer: Option<Renderer>,
-    particles: VecDeque<Particle>,
+    simulation: Simulation,
+    renderer: Option<Renderer<WebGlBackend>>,
     particle_trail_scale: f64,
-    particle_vertex_array: Vec<f32>,
-    gravity_wells: Vec<GravityWell>,
-    gravity_well_mass: f64,
-    borders_are_active: bool,
     should_clear_screen: bool,
-    rng: rand::rngs::ThreadRng,
+    rescale_on_resize: bool,
+    recorder: Option<Recorder>,
+    // While a recording plays back, input to the simulation is ignored
+    // so it plays out exactly as recorded
+    replay: Option<Replay>,
+    // Mouse positions come in as CSS pixels on the canvas, the camera turns them into
+    // world coordinates so the simulation can carry on past the edges of the view
+    camera: Camera,
+    view_size: [f64; 2],
 }
 
 #[wasm_bindgen]
 impl WasmApp {
     pub fn new() -> WasmApp {
+        WasmApp::with_seed(rand::random())
+    }
+
+    // Creates an app whose random particle layouts and colors are
+    // reproducible, the same seed always gives the same results
+    pub fn with_seed(seed: u64) -> WasmApp {
         utils::set_panic_hook();
-        let particles: VecDeque<Particle> = VecDeque::new();
-        let particle_vertex_array: Vec<f32> = Vec::new();
-        let rng = rand::thread_rng();
-        let rust_canvas = WasmApp {
-            width: 0,
-            height: 0,
+        WasmApp {
+            simulation: Simulation::with_seed(0, 0, seed),
             renderer: None,
-            particles,
             particle_trail_scale: 0.1,
-            particle_vertex_array,
-            gravity_wells: Vec::new(),
-            gravity_well_mass: 90.0,
-            borders_are_active: false,
             should_clear_screen: true,
-            rng,
-        };
-        rust_canvas
+            rescale_on_resize: false,
+            recorder: None,
+            replay: None,
+            camera: Camera::fitting(0.0, 0.0),
+            view_size: [0.0, 0.0],
+        }
     }
 
-    pub fn connect_canvas_element(&mut self, canvas: HtmlCanvasElement) -> Result<(), JsValue> {
-        // let document = web_sys::window().unwrap().document().unwrap();
-        // let canvas = document.get_element_by_id("canvas").unwrap();
-        // let canvas: web_sys::HtmlCanvasElement = canvas.dyn_into::<web_sys::HtmlCanvasElement>()?;
+    pub fn reseed(&mut self, seed: u64) {
+        if self.is_replaying() {
+            return;
+        }
+        self.simulation.reseed(seed);
+        self.record(InputEvent::Reseed { seed });
+    }
 
-        self.width = canvas.width();
-        self.height = canvas.height();
+    pub fn get_seed(&self) -> u64 {
+        self.simulation.seed()
+    }
 
-        self.renderer = Some(Renderer::new(&canvas));
+    pub fn connect_canvas_element(&mut self, canvas: HtmlCanvasElement) -> Result<(), JsValue> {
+        self.simulation.set_size(canvas.width(), canvas.height());
+        self.record(InputEvent::Resize {
+            width: canvas.width(),
+            height: canvas.height(),
+            rescale: false,
+        });
+        self.view_size = [canvas.width() as f64, canvas.height() as f64];
+        self.camera = Camera::fitting(self.view_size[0], self.view_size[1]);
+
+        self.renderer = Some(Renderer::new(WebGlBackend::new(&canvas)));
 
         Ok(())
     }
 
-    pub fn initialize_particles(&mut self, num_particles: u32) {
-        // self.particles.reserve(num_particles as usize);
-        // self.particle_vertex_array
-        //     .reserve(num_particles as usize * 12);
-        let min_vel = -80.0;
-        let max_vel = 80.0;
-        for _ in 0..num_particles {
-            let pos_x = self.rng.gen::<f64>() * self.width as f64;
-            let pos_y = self.rng.gen::<f64>() * self.height as f64;
-            let vel_x = self.rng.gen::<f64>() * (max_vel - min_vel) + min_vel;
-            let vel_y = self.rng.gen::<f64>() * (max_vel - min_vel) + min_vel;
-            self.spawn_particle(pos_x, pos_y, vel_x, vel_y);
+    // Resizes the simulation to width by height CSS pixels and the canvas to match,
+    // pixel_ratio is device pixels per CSS pixel (window.devicePixelRatio)
+    // so the canvas stays sharp on high density displays
+    pub fn resize(&mut self, width: u32, height: u32, pixel_ratio: f64) {
+        let rescale = self.rescale_on_resize;
+        let old_size = self.view_size;
+        let new_size = [width as f64, height as f64];
+        if rescale && old_size[0] > 0.0 && old_size[1] > 0.0 {
+            // Looks at the same spot of the scaled world
+            self.camera.center[0] *= new_size[0] / old_size[0];
+            self.camera.center[1] *= new_size[1] / old_size[1];
+        } else {
+            // Keeps the world point in the top left corner where it is
+            self.camera.center[0] += (new_size[0] - old_size[0]) / 2.0 / self.camera.zoom;
+            self.camera.center[1] += (new_size[1] - old_size[1]) / 2.0 / self.camera.zoom;
         }
-    }
-
-    pub fn update(&mut self, mut delta: f64) {
-        let _timer = Timer::new("WasmApp::update()");
-        delta /= 1000.0;
-
-        for well in &mut self.gravity_wells {
-            // rotate gravity well
-            well.rotation_deg += GravityWell::ROTATION_SPEED;
-            well.rotation_deg %= 360.0;
-
-            // calculate and apply gravity force and velocity for each particle
-            for p in &mut self.particles {
-                let p_to_well = vecmath::vec2_sub(well.pos, p.pos);
-                // let distance_squared = f64::max(1.0, f64::powi(vecmath::vec2_len(p_to_well), 2));
-                // let distance_squared = f64::max(1.0, f64::sqrt(vecmath::vec2_len(p_to_well)));
-                let distance_squared = f64::max(1.0, vecmath::vec2_len(p_to_well) / 30.0);
-                let grav_force = self.gravity_well_mass / (distance_squared);
-                // let grav_force = self.gravity_well_mass
-                //     / (distance_squared * f64::sqrt(distance_squared + Self::SOFTENING_CONSTANT));
-                let force_dir = vecmath::vec2_normalized(p_to_well);
-                let acc = vecmath::vec2_scale(force_dir, grav_force);
-                p.vel = vecmath::vec2_add(p.vel, acc);
-                // if vecmath::vec2_len(p.vel) > Particle::MAX_VELOCITY {
-                //     p.vel = vecmath::vec2_scale(
-                //         p.vel,
-                //         Particle::MAX_VELOCITY / vecmath::vec2_len(p.vel),
-                //     );
-                // }
+        self.view_size = new_size;
+        match &mut self.replay {
+            // The replay keeps its recorded size until it stops
+            Some(replay) => replay.set_live_size(width, height),
+            None => {
+                self.simulation.resize(width, height, rescale);
+                self.record(InputEvent::Resize {
+                    width,
+                    height,
+                    rescale,
+                });
             }
         }
+        if let Some(renderer) = &mut self.renderer {
+            renderer.backend.resize(width, height, pixel_ratio);
+        }
+    }
+
+    // Whether resize moves particles, wells and emitters along with the edges,
+    // otherwise everything stays put and only the bounds change
+    pub fn set_rescale_on_resize(&mut self, rescale: bool) {
+        self.rescale_on_resize = rescale;
+    }
 
-        for p in &mut self.particles {
-            p.pos[0] += p.vel[0] * delta;
-            p.pos[1] += p.vel[1] * delta;
+    // Zooms in (factor above 1) or out around a point on the canvas,
+    // which stays over the same spot in the world
+    pub fn zoom_at(&mut self, x: f64, y: f64, factor: f64) {
+        self.camera.zoom_at([x, y], factor, self.view_size);
+    }
 
-            // apply 'drag' to particles
-            // p.vel = vecmath::vec2_scale(p.vel, 0.9995);
-            p.vel = vecmath::vec2_scale(p.vel, 0.99);
-            // p.vel = vecmath::vec2_scale(p.vel, 1.001);
+    // Drags the view by a distance in canvas pixels
+    pub fn pan_by(&mut self, dx: f64, dy: f64) {
+        self.camera.pan_by(dx, dy);
+    }
 
-            if self.borders_are_active {
-                if p.pos[0] < 0.0 || p.pos[0] >= self.width as f64 {
-                    p.vel[0] *= -1.0;
-                    // p.pos[0] = p.pos[0].max(0.0);
-                    p.pos[0] = f64::max(p.pos[0], 0.0);
-                    // p.pos[0] = p.pos[0].min((self.width - p.size) as f64);
-                    p.pos[0] = f64::min(p.pos[0], (self.width - 1) as f64);
-                }
-                if p.pos[1] < 0.0 || p.pos[1] >= self.height as f64 {
-                    p.vel[1] *= -1.0;
-                    // p.pos[1] = p.pos[1].max(0.0);
-                    p.pos[1] = f64::max(p.pos[1], 0.0);
-                    // p.pos[1] = p.pos[1].min((self.height - p.size) as f64);
-                    p.pos[1] = f64::min(p.pos[1], (self.height - 1) as f64);
+    // Goes back to showing the simulation area at its normal size
+    pub fn reset_camera(&mut self) {
+        self.camera = Camera::fitting(self.view_size[0], self.view_size[1]);
+    }
+
+    pub fn get_zoom(&self) -> f64 {
+        self.camera.zoom
+    }
+
+    pub fn initialize_particles(&mut self, num_particles: u32) {
+        if self.is_replaying() {
+            return;
+        }
+        self.simulation.initialize_particles(num_particles);
+        self.record(InputEvent::InitializeParticles { count: num_particles });
+    }
+
+    pub fn update(&mut self, delta: f64) {
+        let _timer = Timer::new("WasmApp::update()");
+        if let Some(recorder) = &mut self.recorder {
+            recorder.sync_settings(&self.simulation);
+        }
+        match &mut self.replay {
+            Some(replay) => {
+                self.simulation
+                    .step_with(delta / 1000.0, |sim| replay.apply_due_events(sim));
+                if replay.is_finished(&self.simulation) {
+                    replay.apply_due_events(&mut self.simulation);
+                    self.stop_replay();
                 }
             }
-
-            // p.color.r = p.color.r.wrapping_add(1);
-            // p.color.g = p.color.g.wrapping_add(1);
-            // p.color.b = p.color.b.wrapping_add(1);
+            None => self.simulation.step(delta / 1000.0),
         }
     }
 
@@ -175,111 +229,281 @@ impl WasmApp {
         match &mut self.renderer {
             None => {
                 console::log_1(&"Error: No renderer".into());
-                return;
             }
             Some(renderer) => {
+                renderer.set_camera(&self.camera);
                 renderer.clear_screen();
 
-                renderer.render_particles(&self.particles, self.particle_trail_scale);
+                renderer.render_particles(self.simulation.particles(), self.particle_trail_scale);
 
-                renderer.render_gravity_wells(&self.gravity_wells);
+                renderer.render_gravity_wells(self.simulation.gravity_wells());
             }
         }
     }
 
+    // Position is in canvas pixels and velocity in canvas pixels per second,
+    // both are converted through the camera
     pub fn spawn_particle(&mut self, x: f64, y: f64, vel_x: f64, vel_y: f64) {
-        // let _timer = Timer::new("WasmApp::spawn_particle");
-        let color = Color {
-            r: self.rng.gen::<u8>(),
-            g: self.rng.gen::<u8>(),
-            b: self.rng.gen::<u8>(),
-            a: 0xff,
-        };
-        self.particles
-            .push_back(Particle::new(x, y, vel_x, vel_y, color));
-        let from_x = x;
-        let from_y = y;
-        let to_x = from_x - (vel_x * self.particle_trail_scale);
-        let to_y = from_y - (vel_y * self.particle_trail_scale);
-        if let Some(renderer) = &mut self.renderer {
-            renderer.particle_vertex_array.append(&mut vec![
-                from_x as f32,
-                from_y as f32,
-                to_x as f32,
-                to_y as f32,
-            ]);
-            renderer.particle_color_array.append(&mut vec![
-                color.r, color.g, color.b, 255, color.r, color.g, color.b, 0,
-            ]);
+        if self.is_replaying() {
+            return;
         }
+        let [x, y] = self.screen_to_world(x, y);
+        let (vel_x, vel_y) = (vel_x / self.camera.zoom, vel_y / self.camera.zoom);
+        self.simulation.spawn_particle(x, y, vel_x, vel_y);
+        self.record(InputEvent::SpawnParticle { x, y, vel_x, vel_y });
     }
 
     pub fn spawn_gravity_well(&mut self, x: f64, y: f64) {
-        self.gravity_wells.push(GravityWell::new([x, y], 200.0));
+        self.spawn_gravity_well_of_kind(x, y, WellKind::Attractor);
+    }
+
+    pub fn spawn_gravity_well_of_kind(&mut self, x: f64, y: f64, kind: WellKind) {
+        if self.is_replaying() {
+            return;
+        }
+        let [x, y] = self.screen_to_world(x, y);
+        self.simulation.spawn_gravity_well_of_kind(x, y, kind);
+        self.record(InputEvent::SpawnGravityWell { x, y, kind });
+    }
+
+    // Adds an emitter at a point on the canvas and returns the id used to configure
+    // or remove it later, or nothing while a recording is replaying
+    pub fn add_emitter(&mut self, shape: EmitterShape, x: f64, y: f64) -> Option<u32> {
+        if self.is_replaying() {
+            return None;
+        }
+        let [x, y] = self.screen_to_world(x, y);
+        let id = self.simulation.add_emitter(shape, x, y);
+        self.record(InputEvent::AddEmitter { shape, x, y });
+        Some(id)
+    }
+
+    pub fn remove_emitter(&mut self, id: u32) -> bool {
+        if self.is_replaying() {
+            return false;
+        }
+        let removed = self.simulation.remove_emitter(id);
+        self.record(InputEvent::RemoveEmitter { id });
+        removed
+    }
+
+    pub fn get_emitter_count(&self) -> usize {
+        self.simulation.emitters().len()
+    }
+
+    // Moves an emitter to a point on the canvas.
+    // The emitter setters return false for non-finite values and leave it alone
+    pub fn move_emitter(&mut self, id: u32, x: f64, y: f64) -> bool {
+        if !(x.is_finite() && y.is_finite()) {
+            return false;
+        }
+        let pos = self.screen_to_world(x, y);
+        self.edit_emitter(id, |emitter| emitter.pos = pos)
+    }
+
+    // Line end offset, circle radius (width only) or rectangle dimensions,
+    // in canvas pixels like move_emitter
+    pub fn set_emitter_size(&mut self, id: u32, width: f64, height: f64) -> bool {
+        if !(width.is_finite() && height.is_finite()) {
+            return false;
+        }
+        let size = [width / self.camera.zoom, height / self.camera.zoom];
+        self.edit_emitter(id, |emitter| emitter.size = size)
+    }
+
+    // Particles spawned per second, up to Emitter::MAX_RATE
+    pub fn set_emitter_rate(&mut self, id: u32, rate: f64) -> bool {
+        if !rate.is_finite() {
+            return false;
+        }
+        self.edit_emitter(id, |emitter| emitter.rate = rate.clamp(0.0, Emitter::MAX_RATE))
+    }
+
+    // Launch speed in canvas pixels per second and direction in degrees,
+    // each randomized by up to +/- its spread
+    pub fn set_emitter_velocity(
+        &mut self,
+        id: u32,
+        speed: f64,
+        speed_spread: f64,
+        angle_deg: f64,
+        angle_spread_deg: f64,
+    ) -> bool {
+        let values = [speed, speed_spread, angle_deg, angle_spread_deg];
+        if !values.iter().all(|v| v.is_finite()) {
+            return false;
+        }
+        let (speed, speed_spread) = (speed / self.camera.zoom, speed_spread / self.camera.zoom);
+        self.edit_emitter(id, |emitter| {
+            emitter.speed = speed;
+            emitter.speed_spread = speed_spread;
+            emitter.angle_deg = angle_deg;
+            emitter.angle_spread_deg = angle_spread_deg;
+        })
     }
 
-    // TODO maybe have WasmApp hold onto a reference to the ONE selected gravity well
+    // Colors are given as RRGGBBAA, an empty palette means random colors
+    pub fn set_emitter_palette(&mut self, id: u32, colors: Vec<u32>) -> bool {
+        self.edit_emitter(id, |emitter| {
+            emitter.palette = colors.into_iter().map(Color::from_u32).collect()
+        })
+    }
+
+    // Lifetime in seconds of spawned particles, zero means forever
+    pub fn set_emitter_lifetime(&mut self, id: u32, seconds: f64) -> bool {
+        self.edit_emitter(id, |emitter| {
+            emitter.lifetime = if seconds > 0.0 { seconds } else { f64::INFINITY }
+        })
+    }
+
+    // TODO maybe have Simulation hold onto a reference to the ONE selected gravity well
     // hopefully will be easier to prevent multiple hover/selection highlights at once
-    pub fn try_selecting(&mut self, x: i32, y: i32) -> bool {
-        let mut found = false;
-        for well in self.gravity_wells.iter_mut().rev() {
-            if well.is_point_inside(x, y) && !found {
-                well.is_selected = true;
-                found = true;
-            } else {
-                well.is_selected = false;
-            }
+    // Hovering calls this on every mouse move, so it's only recorded when the selection changes
+    pub fn try_selecting(&mut self, x: f64, y: f64) -> bool {
+        if self.is_replaying() {
+            return false;
+        }
+        let [x, y] = self.screen_to_world(x, y);
+        let selected = self.simulation.selected_well_index();
+        let found = self.simulation.try_selecting(x, y);
+        if self.simulation.selected_well_index() != selected {
+            self.record(InputEvent::TrySelecting { x, y });
         }
         found
     }
 
+    // Only recorded when there was a selection to release, like try_selecting
     pub fn release_selection(&mut self) {
-        for well in &mut self.gravity_wells {
-            well.is_selected = false;
+        if self.is_replaying() {
+            return;
+        }
+        let selected = self.simulation.selected_well_index();
+        self.simulation.release_selection();
+        if selected.is_some() {
+            self.record(InputEvent::ReleaseSelection);
         }
     }
 
+    // Distance is in canvas pixels, so a dragged well keeps up with the mouse at any zoom
     pub fn move_selection_by(&mut self, x: f64, y: f64) {
-        for well in &mut self.gravity_wells {
-            if well.is_selected {
-                well.pos[0] += x;
-                well.pos[1] += y;
-            }
+        if self.is_replaying() {
+            return;
         }
+        let (x, y) = (x / self.camera.zoom, y / self.camera.zoom);
+        self.simulation.move_selection_by(x, y);
+        self.record(InputEvent::MoveSelectionBy { x, y });
     }
 
     pub fn try_removing(&mut self, x: f64, y: f64) {
-        for i in 0..self.gravity_wells.len() as usize {
-            if self.gravity_wells[i].is_point_inside(x as i32, y as i32) {
-                self.gravity_wells.remove(i);
-                return;
-            }
+        if self.is_replaying() {
+            return;
         }
+        let [x, y] = self.screen_to_world(x, y);
+        self.simulation.try_removing(x, y);
+        self.record(InputEvent::TryRemoving { x, y });
+    }
+
+    // Undoes the last gravity well creation, deletion, move or mass change,
+    // returns false if there is nothing left to undo
+    pub fn undo(&mut self) -> bool {
+        if self.is_replaying() {
+            return false;
+        }
+        let undone = self.simulation.undo();
+        self.record(InputEvent::Undo);
+        undone
+    }
+
+    pub fn redo(&mut self) -> bool {
+        if self.is_replaying() {
+            return false;
+        }
+        let redone = self.simulation.redo();
+        self.record(InputEvent::Redo);
+        redone
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.simulation.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.simulation.can_redo()
     }
 
     pub fn clear_particles(&mut self) {
-        self.particles.clear();
-        if let Some(renderer) = &mut self.renderer {
-            renderer.particle_color_array.clear();
-            renderer.particle_vertex_array.clear();
+        if self.is_replaying() {
+            return;
         }
+        self.simulation.clear_particles();
+        self.record(InputEvent::ClearParticles);
     }
 
     pub fn remove_particles(&mut self, num_to_remove: usize) {
-        let num_to_remove = usize::min(self.particles.len(), num_to_remove);
-        drop(self.particles.drain(0..num_to_remove));
+        if self.is_replaying() {
+            return;
+        }
+        self.simulation.remove_particles(num_to_remove);
+        self.record(InputEvent::RemoveParticles {
+            count: num_to_remove,
+        });
+    }
+
+    pub fn set_gravity_well_mass_multiplier(&mut self, multiplier: f64) {
+        self.simulation.set_gravity_well_mass_multiplier(multiplier);
+    }
+
+    pub fn get_gravity_well_mass_multiplier(&self) -> f64 {
+        self.simulation.get_gravity_well_mass_multiplier()
+    }
+
+    pub fn get_gravity_well_count(&self) -> usize {
+        self.simulation.gravity_wells().len()
+    }
+
+    pub fn get_gravity_well_mass_at(&self, index: usize) -> Option<f64> {
+        self.simulation.get_well_mass(index)
+    }
+
+    pub fn set_gravity_well_mass_at(&mut self, index: usize, mass: f64) -> bool {
+        if self.is_replaying() {
+            return false;
+        }
+        let found = self.simulation.set_well_mass(index, mass);
+        self.record(InputEvent::SetWellMass { index, mass });
+        found
     }
 
-    pub fn set_gravity_well_mass(&mut self, new_mass: f64) {
-        self.gravity_well_mass = new_mass;
+    pub fn get_selected_gravity_well_mass(&self) -> Option<f64> {
+        self.simulation
+            .selected_well_index()
+            .and_then(|i| self.simulation.get_well_mass(i))
     }
 
-    pub fn get_gravity_well_mass(&self) -> f64 {
-        self.gravity_well_mass
+    pub fn set_selected_gravity_well_mass(&mut self, mass: f64) -> bool {
+        match self.simulation.selected_well_index() {
+            Some(i) => self.set_gravity_well_mass_at(i, mass),
+            None => false,
+        }
     }
 
     pub fn get_particle_count(&self) -> usize {
-        self.particles.len()
+        self.simulation.particles().len()
+    }
+
+    // Lifetime in seconds of newly spawned particles, zero means forever
+    pub fn set_particle_lifetime(&mut self, seconds: f64) {
+        self.simulation.set_particle_lifetime(seconds);
+    }
+
+    // Returns zero for particles that live forever
+    pub fn get_particle_lifetime(&self) -> f64 {
+        let lifetime = self.simulation.get_particle_lifetime();
+        if lifetime.is_finite() {
+            lifetime
+        } else {
+            0.0
+        }
     }
 
     pub fn set_particle_trail_scale(&mut self, scale: f64) {
@@ -290,42 +514,322 @@ impl WasmApp {
         self.particle_trail_scale
     }
 
-    pub fn set_borders_active(&mut self, new_state: bool) {
-        if new_state == true {
-            // let mut particle_indices_to_delete = Vec::new();
-            // for i in 0..self.particles.len() {
-            //     let p = &self.particles[i];
-            //     if p.pos[0] < 0.0
-            //         || p.pos[1] < 0.0
-            //         || p.pos[1] >= (self.height - p.size) as f64
-            //     {
-            //         particle_indices_to_delete.push(i);
-            //     }
-            // }
-            // for idx in particle_indices_to_delete.iter().rev().copied() {
-            //     self.particles.remove(idx);
-            // }
-            // let width = self.width;
-            // let height = self.height;
-            // self.particles.retain(|p| {
-            //     p.pos[0] >= 0.0
-            //         && p.pos[0] < (width - p.size) as f64
-            //         && p.pos[1] >= 0.0
-            //         && p.pos[1] < (height - p.size) as f64
-            // });
-            for i in (0..self.particles.len()).rev() {
-                let p = &self.particles[i];
-                if p.pos[0] < 0.0
-                    || p.pos[0] >= (self.width - 1) as f64
-                    || p.pos[1] < 0.0
-                    || p.pos[1] >= (self.height - 1) as f64
-                {
-                    self.particles.swap_remove_back(i);
-                }
-            }
+    pub fn set_force_law(&mut self, law: ForceLaw) {
+        let mut config = self.simulation.force_config();
+        config.law = law;
+        self.simulation.set_force_config(config);
+    }
+
+    pub fn get_force_law(&self) -> ForceLaw {
+        self.simulation.force_config().law
+    }
+
+    // Softening length used by the inverse square and power laws,
+    // in units of the distance scale
+    pub fn set_force_softening(&mut self, softening: f64) {
+        let mut config = self.simulation.force_config();
+        config.softening = softening.abs();
+        self.simulation.set_force_config(config);
+    }
+
+    pub fn get_force_softening(&self) -> f64 {
+        self.simulation.force_config().softening
+    }
+
+    // How many pixels count as one unit of distance in the force law,
+    // non-positive scales are ignored
+    pub fn set_force_distance_scale(&mut self, distance_scale: f64) {
+        if distance_scale > 0.0 {
+            let mut config = self.simulation.force_config();
+            config.distance_scale = distance_scale;
+            self.simulation.set_force_config(config);
         }
+    }
+
+    pub fn get_force_distance_scale(&self) -> f64 {
+        self.simulation.force_config().distance_scale
+    }
+
+    // Exponent used by the power law
+    pub fn set_force_exponent(&mut self, exponent: f64) {
+        let mut config = self.simulation.force_config();
+        config.exponent = exponent;
+        self.simulation.set_force_config(config);
+    }
+
+    pub fn get_force_exponent(&self) -> f64 {
+        self.simulation.force_config().exponent
+    }
 
-        self.borders_are_active = new_state;
+    pub fn set_drag_model(&mut self, model: DragModel) {
+        let mut config = self.simulation.drag_config();
+        config.model = model;
+        self.simulation.set_drag_config(config);
+    }
+
+    pub fn get_drag_model(&self) -> DragModel {
+        self.simulation.drag_config().model
+    }
+
+    // Strength of the current drag model, per second for linear drag
+    // and per pixel for quadratic drag. Each model remembers its own
+    pub fn set_drag_coefficient(&mut self, coefficient: f64) {
+        let mut config = self.simulation.drag_config();
+        config.set_coefficient(coefficient.max(0.0));
+        self.simulation.set_drag_config(config);
+    }
+
+    pub fn get_drag_coefficient(&self) -> f64 {
+        self.simulation.drag_config().coefficient()
+    }
+
+    pub fn set_speed_limit_mode(&mut self, mode: SpeedLimitMode) {
+        let mut speed_limit = self.simulation.speed_limit();
+        speed_limit.mode = mode;
+        self.simulation.set_speed_limit(speed_limit);
+    }
+
+    pub fn get_speed_limit_mode(&self) -> SpeedLimitMode {
+        self.simulation.speed_limit().mode
+    }
+
+    // Maximum particle speed in pixels per second, non-positive limits are ignored
+    pub fn set_max_particle_speed(&mut self, max_speed: f64) {
+        if max_speed > 0.0 {
+            let mut speed_limit = self.simulation.speed_limit();
+            speed_limit.max_speed = max_speed;
+            self.simulation.set_speed_limit(speed_limit);
+        }
+    }
+
+    pub fn get_max_particle_speed(&self) -> f64 {
+        self.simulation.speed_limit().max_speed
+    }
+
+    // Toggles particles attracting each other
+    pub fn set_nbody_enabled(&mut self, enabled: bool) {
+        let mut config = self.simulation.nbody_config();
+        config.enabled = enabled;
+        self.simulation.set_nbody_config(config);
+    }
+
+    pub fn get_nbody_enabled(&self) -> bool {
+        self.simulation.nbody_config().enabled
+    }
+
+    // Barnes-Hut accuracy parameter, 0 is exact and larger values are faster
+    pub fn set_nbody_theta(&mut self, theta: f64) {
+        let mut config = self.simulation.nbody_config();
+        config.theta = theta.max(0.0);
+        self.simulation.set_nbody_config(config);
+    }
+
+    pub fn get_nbody_theta(&self) -> f64 {
+        self.simulation.nbody_config().theta
+    }
+
+    pub fn set_nbody_strength(&mut self, strength: f64) {
+        let mut config = self.simulation.nbody_config();
+        config.strength = strength;
+        self.simulation.set_nbody_config(config);
+    }
+
+    pub fn get_nbody_strength(&self) -> f64 {
+        self.simulation.nbody_config().strength
+    }
+
+    pub fn set_nbody_softening(&mut self, softening: f64) {
+        let mut config = self.simulation.nbody_config();
+        config.softening = softening.abs();
+        self.simulation.set_nbody_config(config);
+    }
+
+    pub fn get_nbody_softening(&self) -> f64 {
+        self.simulation.nbody_config().softening
+    }
+
+    pub fn set_collisions_enabled(&mut self, enabled: bool) {
+        let mut config = self.simulation.collision_config();
+        config.collisions_enabled = enabled;
+        self.simulation.set_collision_config(config);
+    }
+
+    pub fn get_collisions_enabled(&self) -> bool {
+        self.simulation.collision_config().collisions_enabled
+    }
+
+    // Radius in pixels of each particle when colliding
+    pub fn set_collision_radius(&mut self, radius: f64) {
+        let mut config = self.simulation.collision_config();
+        config.radius = radius.max(0.0);
+        self.simulation.set_collision_config(config);
+    }
+
+    pub fn get_collision_radius(&self) -> f64 {
+        self.simulation.collision_config().radius
+    }
+
+    // How hard close particles push each other apart, zero turns it off
+    pub fn set_separation_strength(&mut self, strength: f64) {
+        let mut config = self.simulation.collision_config();
+        config.separation_strength = strength;
+        self.simulation.set_collision_config(config);
+    }
+
+    pub fn get_separation_strength(&self) -> f64 {
+        self.simulation.collision_config().separation_strength
+    }
+
+    pub fn set_separation_radius(&mut self, radius: f64) {
+        let mut config = self.simulation.collision_config();
+        config.separation_radius = radius.max(0.0);
+        self.simulation.set_collision_config(config);
+    }
+
+    pub fn get_separation_radius(&self) -> f64 {
+        self.simulation.collision_config().separation_radius
+    }
+
+    // How many particles are within radius of (x, y), e.g. around the mouse.
+    // Both are in canvas pixels, so the area counted shrinks in the world when zoomed in
+    pub fn count_particles_near(&mut self, x: f64, y: f64, radius: f64) -> usize {
+        let [x, y] = self.screen_to_world(x, y);
+        let radius = radius / self.camera.zoom;
+        self.simulation.particles_within(x, y, radius).len()
+    }
+
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        self.simulation.set_integrator(integrator);
+    }
+
+    pub fn get_integrator(&self) -> Integrator {
+        self.simulation.get_integrator()
+    }
+
+    // Length of one physics tick in milliseconds, update() runs as many
+    // of these as fit in the time it is given
+    pub fn set_fixed_timestep(&mut self, milliseconds: f64) {
+        self.simulation.set_fixed_timestep(milliseconds / 1000.0);
+    }
+
+    pub fn get_fixed_timestep(&self) -> f64 {
+        self.simulation.get_fixed_timestep() * 1000.0
+    }
+
+    // Shorthand for switching between bouncing off the borders and open borders
+    pub fn set_borders_active(&mut self, new_state: bool) {
+        let mode = if new_state {
+            BorderMode::Bounce
+        } else {
+            BorderMode::Open
+        };
+        self.simulation.set_border_mode(mode);
+    }
+
+    pub fn set_border_mode(&mut self, mode: BorderMode) {
+        self.simulation.set_border_mode(mode);
+    }
+
+    pub fn get_border_mode(&self) -> BorderMode {
+        self.simulation.borders().mode
+    }
+
+    // Fraction of speed particles keep when bouncing off a border, clamped to [0, 1]
+    pub fn set_border_restitution(&mut self, restitution: f64) {
+        let mut borders = self.simulation.borders();
+        borders.restitution = restitution.clamp(0.0, 1.0);
+        self.simulation.set_borders(borders);
+    }
+
+    pub fn get_border_restitution(&self) -> f64 {
+        self.simulation.borders().restitution
+    }
+
+    // Everything needed to recreate the current scene, as JSON
+    pub fn export_scene(&self) -> String {
+        let mut scene = self.simulation.to_scene();
+        scene.view = ViewSettings {
+            particle_trail_scale: self.particle_trail_scale,
+            should_clear_screen: self.should_clear_screen,
+        };
+        scene.to_json()
+    }
+
+    // Replaces the current scene with one from export_scene.
+    // If the JSON can't be loaded the current scene is kept and the error says why
+    pub fn import_scene(&mut self, json: &str) -> Result<(), JsValue> {
+        self.check_not_replaying()?;
+        let scene = Scene::from_json(json).map_err(|err| JsValue::from_str(&err.to_string()))?;
+        self.particle_trail_scale = scene.view.particle_trail_scale;
+        self.should_clear_screen = scene.view.should_clear_screen;
+        self.simulation.load_scene(scene.clone());
+        self.record(InputEvent::LoadScene { scene });
+        Ok(())
+    }
+
+    // Compact binary copy of the whole simulation, for rewinding,
+    // checkpointing or handing the state over to another tab or worker
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.simulation.snapshot()
+    }
+
+    // Restores a snapshot taken with snapshot(). The snapshot's magic, version and
+    // lengths are checked first, if anything is wrong the current state is kept
+    pub fn restore(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        self.check_not_replaying()?;
+        self.simulation
+            .restore(bytes)
+            .map_err(|err| JsValue::from_str(&err.to_string()))?;
+        self.record(InputEvent::Restore {
+            snapshot: bytes.to_vec(),
+        });
+        Ok(())
+    }
+
+    // Starts recording every input along with the tick it happened on.
+    // The simulation is reset to exactly the state a replay will start from,
+    // which also clears the undo history. Fails while a recording is replaying,
+    // since the replayed input isn't the user's to record
+    pub fn start_recording(&mut self) -> Result<(), JsValue> {
+        self.check_not_replaying()?;
+        self.recorder = Some(Recorder::start(&mut self.simulation));
+        Ok(())
+    }
+
+    // Stops recording and returns the recording as JSON,
+    // or nothing if no recording was in progress
+    pub fn stop_recording(&mut self) -> Option<String> {
+        let recorder = self.recorder.take()?;
+        Some(recorder.finish(&self.simulation).to_json())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    // Resets the simulation to the start of a recording from stop_recording and
+    // plays its input back as update() is called, until the recording runs out.
+    // Any recording in progress is stopped and thrown away, it couldn't be
+    // replayed itself once the simulation jumps to another recording's start
+    pub fn replay(&mut self, json: &str) -> Result<(), JsValue> {
+        let recording =
+            Recording::from_json(json).map_err(|err| JsValue::from_str(&err.to_string()))?;
+        self.recorder = None;
+        self.stop_replay();
+        self.replay = Some(Replay::start(recording, &mut self.simulation));
+        Ok(())
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    // Stops playing back a recording, leaving the simulation where it got to
+    // but back at the size of the canvas
+    pub fn stop_replay(&mut self) {
+        if let Some(replay) = self.replay.take() {
+            replay.stop(&mut self.simulation);
+        }
     }
 
     pub fn set_should_clear_screen(&mut self, new_state: bool) {
@@ -334,11 +838,13 @@ impl WasmApp {
 
     pub fn add_texture_from_image(&mut self, name: String, image: &HtmlImageElement) {
         if let Some(renderer) = &mut self.renderer {
-            let texture = renderer.context.create_texture();
+            let texture = renderer.backend.context.create_texture();
             renderer
+                .backend
                 .context
                 .bind_texture(WebGlRenderingContext::TEXTURE_2D, texture.as_ref());
             renderer
+                .backend
                 .context
                 .tex_image_2d_with_u32_and_u32_and_image(
                     WebGlRenderingContext::TEXTURE_2D,
@@ -351,34 +857,78 @@ impl WasmApp {
                 .expect("failed to buffer image data to gravity well texture");
             if is_power_of_2(image.width()) && is_power_of_2(image.height()) {
                 renderer
+                    .backend
                     .context
                     .generate_mipmap(WebGlRenderingContext::TEXTURE_2D);
             } else {
-                renderer.context.tex_parameteri(
+                renderer.backend.context.tex_parameteri(
                     WebGlRenderingContext::TEXTURE_2D,
                     WebGlRenderingContext::TEXTURE_WRAP_S,
                     WebGlRenderingContext::CLAMP_TO_EDGE as i32,
                 );
-                renderer.context.tex_parameteri(
+                renderer.backend.context.tex_parameteri(
                     WebGlRenderingContext::TEXTURE_2D,
                     WebGlRenderingContext::TEXTURE_WRAP_T,
                     WebGlRenderingContext::CLAMP_TO_EDGE as i32,
                 );
-                renderer.context.tex_parameteri(
+                renderer.backend.context.tex_parameteri(
                     WebGlRenderingContext::TEXTURE_2D,
                     WebGlRenderingContext::TEXTURE_MIN_FILTER,
                     WebGlRenderingContext::LINEAR as i32,
                 );
             }
-            renderer.textures.insert(name, texture);
+            renderer.backend.textures.insert(name, texture);
         }
     }
 }
 
-fn is_power_of_2(n: u32) -> bool {
-    if (n & (n - 1)) == 0 {
-        true
-    } else {
-        false
+impl Default for WasmApp {
+    fn default() -> Self {
+        Self::new()
     }
 }
+
+impl WasmApp {
+    fn screen_to_world(&self, x: f64, y: f64) -> [f64; 2] {
+        self.camera.screen_to_world([x, y], self.view_size)
+    }
+
+    fn check_not_replaying(&self) -> Result<(), JsValue> {
+        if self.is_replaying() {
+            return Err(JsValue::from_str("Can't change the simulation while a recording is replaying"));
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, event: InputEvent) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(&self.simulation, event);
+        }
+    }
+
+    // Applies edit to the emitter with the given id,
+    // returns false if there is no such emitter
+    fn edit_emitter<F>(&mut self, id: u32, edit: F) -> bool
+    where
+        F: FnOnce(&mut Emitter),
+    {
+        if self.is_replaying() {
+            return false;
+        }
+        match self.simulation.emitter_mut(id) {
+            Some(emitter) => {
+                edit(emitter);
+                if self.recorder.is_some() {
+                    let emitter = emitter.clone();
+                    self.record(InputEvent::SetEmitter { emitter });
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn is_power_of_2(n: u32) -> bool {
+    n.is_power_of_two()
+}
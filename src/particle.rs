@@ -1,21 +1,260 @@
-// Simple particle struct to keep track of individual position, velocity, and color
+// Simple particle struct to keep track of individual position, velocity, color,
+// and how long it has been alive
 
 use crate::color::Color;
+use serde::{Deserialize, Serialize};
 
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct Particle {
     pub pos: [f64; 2],
     pub vel: [f64; 2],
     pub color: Color,
+    // Seconds since the particle was spawned
+    pub age: f64,
+    // Seconds the particle lives for, infinite for particles that never expire
+    #[serde(with = "crate::scene::infinite_as_null")]
+    pub lifetime: f64,
 }
 
 impl Particle {
-    const MAX_VELOCITY: f64 = 2000.0;
+    pub const MAX_VELOCITY: f64 = 2000.0;
     pub fn new(pos_x: f64, pos_y: f64, vel_x: f64, vel_y: f64, color: Color) -> Particle {
         Particle {
             pos: [pos_x, pos_y],
             vel: [vel_x, vel_y],
             color,
+            age: 0.0,
+            lifetime: f64::INFINITY,
         }
     }
+
+    pub fn is_expired(&self) -> bool {
+        self.age >= self.lifetime
+    }
+
+    // Fades linearly from 1.0 when spawned to 0.0 when the particle expires
+    pub fn opacity(&self) -> f64 {
+        (1.0 - self.age / self.lifetime).clamp(0.0, 1.0)
+    }
+}
+
+// Struct-of-arrays storage for every particle in the simulation.
+// Keeping each attribute in its own contiguous buffer lets the update passes
+// stream through just the data they need, and lets the renderer read positions
+// and colors straight out of tightly packed arrays.
+// Particles are kept in spawn order, oldest first. The buffers are private so they
+// always stay the same length, callers get slices of them instead.
+#[derive(Default)]
+pub struct Particles {
+    pos_x: Vec<f32>,
+    pos_y: Vec<f32>,
+    vel_x: Vec<f32>,
+    vel_y: Vec<f32>,
+    colors: Vec<Color>,
+    ages: Vec<f32>,
+    lifetimes: Vec<f32>,
+}
+
+// Mutable slices of the position and velocity buffers, all of the same length
+pub(crate) struct Motion<'a> {
+    pub pos_x: &'a mut [f32],
+    pub pos_y: &'a mut [f32],
+    pub vel_x: &'a mut [f32],
+    pub vel_y: &'a mut [f32],
 }
 
+impl Particles {
+    pub fn new() -> Self {
+        Particles::default()
+    }
+
+    // Builds particles from one buffer per attribute, returns None if their lengths differ
+    pub(crate) fn from_buffers(
+        pos_x: Vec<f32>,
+        pos_y: Vec<f32>,
+        vel_x: Vec<f32>,
+        vel_y: Vec<f32>,
+        colors: Vec<Color>,
+        ages: Vec<f32>,
+        lifetimes: Vec<f32>,
+    ) -> Option<Self> {
+        let len = pos_x.len();
+        let lengths = [pos_y.len(), vel_x.len(), vel_y.len(), colors.len(), ages.len(), lifetimes.len()];
+        if lengths.iter().any(|&other| other != len) {
+            return None;
+        }
+        Some(Particles {
+            pos_x,
+            pos_y,
+            vel_x,
+            vel_y,
+            colors,
+            ages,
+            lifetimes,
+        })
+    }
+
+    pub fn pos_x(&self) -> &[f32] {
+        &self.pos_x
+    }
+
+    pub fn pos_y(&self) -> &[f32] {
+        &self.pos_y
+    }
+
+    pub fn vel_x(&self) -> &[f32] {
+        &self.vel_x
+    }
+
+    pub fn vel_y(&self) -> &[f32] {
+        &self.vel_y
+    }
+
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    pub fn ages(&self) -> &[f32] {
+        &self.ages
+    }
+
+    pub fn lifetimes(&self) -> &[f32] {
+        &self.lifetimes
+    }
+
+    pub(crate) fn motion_mut(&mut self) -> Motion<'_> {
+        Motion {
+            pos_x: &mut self.pos_x,
+            pos_y: &mut self.pos_y,
+            vel_x: &mut self.vel_x,
+            vel_y: &mut self.vel_y,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pos_x.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos_x.is_empty()
+    }
+
+    pub fn push(&mut self, particle: &Particle) {
+        self.pos_x.push(particle.pos[0] as f32);
+        self.pos_y.push(particle.pos[1] as f32);
+        self.vel_x.push(particle.vel[0] as f32);
+        self.vel_y.push(particle.vel[1] as f32);
+        self.colors.push(particle.color);
+        self.ages.push(particle.age as f32);
+        self.lifetimes.push(particle.lifetime as f32);
+    }
+
+    // Copies out the particle at index i
+    pub fn get(&self, i: usize) -> Particle {
+        Particle {
+            pos: self.pos(i),
+            vel: self.vel(i),
+            color: self.colors[i],
+            age: self.ages[i] as f64,
+            lifetime: self.lifetimes[i] as f64,
+        }
+    }
+
+    pub fn set(&mut self, i: usize, particle: &Particle) {
+        self.set_pos(i, particle.pos);
+        self.set_vel(i, particle.vel);
+        self.colors[i] = particle.color;
+        self.ages[i] = particle.age as f32;
+        self.lifetimes[i] = particle.lifetime as f32;
+    }
+
+    pub fn pos(&self, i: usize) -> [f64; 2] {
+        [self.pos_x[i] as f64, self.pos_y[i] as f64]
+    }
+
+    pub fn set_pos(&mut self, i: usize, pos: [f64; 2]) {
+        self.pos_x[i] = pos[0] as f32;
+        self.pos_y[i] = pos[1] as f32;
+    }
+
+    pub fn vel(&self, i: usize) -> [f64; 2] {
+        [self.vel_x[i] as f64, self.vel_y[i] as f64]
+    }
+
+    pub fn set_vel(&mut self, i: usize, vel: [f64; 2]) {
+        self.vel_x[i] = vel[0] as f32;
+        self.vel_y[i] = vel[1] as f32;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Particle> + '_ {
+        (0..self.len()).map(move |i| self.get(i))
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = [f64; 2]> + Clone + '_ {
+        self.pos_x
+            .iter()
+            .zip(&self.pos_y)
+            .map(|(x, y)| [*x as f64, *y as f64])
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    // Removes the num_to_remove oldest particles
+    pub fn remove_oldest(&mut self, num_to_remove: usize) {
+        let n = usize::min(self.len(), num_to_remove);
+        self.pos_x.drain(0..n);
+        self.pos_y.drain(0..n);
+        self.vel_x.drain(0..n);
+        self.vel_y.drain(0..n);
+        self.colors.drain(0..n);
+        self.ages.drain(0..n);
+        self.lifetimes.drain(0..n);
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.pos_x.truncate(len);
+        self.pos_y.truncate(len);
+        self.vel_x.truncate(len);
+        self.vel_y.truncate(len);
+        self.colors.truncate(len);
+        self.ages.truncate(len);
+        self.lifetimes.truncate(len);
+    }
+
+    // Ages every particle by dt seconds and removes the ones that expire. The rest have
+    // their position and velocity passed to f, and are removed too if it returns false.
+    // Survivors keep their order, and nothing is moved until something has been removed
+    pub fn age_and_retain<F>(&mut self, dt: f64, mut f: F)
+    where
+        F: FnMut(&mut [f64; 2], &mut [f64; 2]) -> bool,
+    {
+        let len = self.len();
+        let mut kept = 0;
+        for i in 0..len {
+            let age = self.ages[i] as f64 + dt;
+            if age >= self.lifetimes[i] as f64 {
+                continue;
+            }
+            let mut pos = [self.pos_x[i] as f64, self.pos_y[i] as f64];
+            let mut vel = [self.vel_x[i] as f64, self.vel_y[i] as f64];
+            if !f(&mut pos, &mut vel) {
+                continue;
+            }
+            if kept != i {
+                self.colors[kept] = self.colors[i];
+                self.lifetimes[kept] = self.lifetimes[i];
+            }
+            self.pos_x[kept] = pos[0] as f32;
+            self.pos_y[kept] = pos[1] as f32;
+            self.vel_x[kept] = vel[0] as f32;
+            self.vel_y[kept] = vel[1] as f32;
+            self.ages[kept] = age as f32;
+            kept += 1;
+        }
+        if kept < len {
+            self.truncate(kept);
+        }
+    }
+}
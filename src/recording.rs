@@ -0,0 +1,288 @@
+// Recording of every input made to a simulation, stamped with the tick it happened on,
+// so a session can be replayed exactly against the same starting scene.
+// Setting changes aren't recorded call by call, instead the full settings are
+// recorded whenever they differ from the last recorded ones.
+
+use crate::emitter::{Emitter, EmitterShape};
+use crate::gravity_well::WellKind;
+use crate::scene::{Scene, SceneError, SimulationSettings};
+use crate::simulation::Simulation;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const RECORDING_VERSION: u32 = 1;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum InputEvent {
+    InitializeParticles { count: u32 },
+    SpawnParticle { x: f64, y: f64, vel_x: f64, vel_y: f64 },
+    ClearParticles,
+    RemoveParticles { count: usize },
+    SpawnGravityWell { x: f64, y: f64, kind: WellKind },
+    TrySelecting { x: f64, y: f64 },
+    ReleaseSelection,
+    MoveSelectionBy { x: f64, y: f64 },
+    TryRemoving { x: f64, y: f64 },
+    SetWellMass { index: usize, mass: f64 },
+    Undo,
+    Redo,
+    AddEmitter { shape: EmitterShape, x: f64, y: f64 },
+    RemoveEmitter { id: u32 },
+    // Replaces the configuration of the emitter with the same id
+    SetEmitter { emitter: Emitter },
+    SetSettings { settings: SimulationSettings },
+    Resize {
+        width: u32,
+        height: u32,
+        // Whether positions were scaled to the new size
+        #[serde(default)]
+        rescale: bool,
+    },
+    Reseed { seed: u64 },
+    LoadScene { scene: Scene },
+    Restore { snapshot: Vec<u8> },
+}
+
+impl InputEvent {
+    pub fn apply(&self, sim: &mut Simulation) {
+        match self {
+            InputEvent::InitializeParticles { count } => sim.initialize_particles(*count),
+            InputEvent::SpawnParticle { x, y, vel_x, vel_y } => sim.spawn_particle(*x, *y, *vel_x, *vel_y),
+            InputEvent::ClearParticles => sim.clear_particles(),
+            InputEvent::RemoveParticles { count } => sim.remove_particles(*count),
+            InputEvent::SpawnGravityWell { x, y, kind } => sim.spawn_gravity_well_of_kind(*x, *y, *kind),
+            InputEvent::TrySelecting { x, y } => {
+                sim.try_selecting(*x, *y);
+            }
+            InputEvent::ReleaseSelection => sim.release_selection(),
+            InputEvent::MoveSelectionBy { x, y } => sim.move_selection_by(*x, *y),
+            InputEvent::TryRemoving { x, y } => sim.try_removing(*x, *y),
+            InputEvent::SetWellMass { index, mass } => {
+                sim.set_well_mass(*index, *mass);
+            }
+            InputEvent::Undo => {
+                sim.undo();
+            }
+            InputEvent::Redo => {
+                sim.redo();
+            }
+            InputEvent::AddEmitter { shape, x, y } => {
+                sim.add_emitter(*shape, *x, *y);
+            }
+            InputEvent::RemoveEmitter { id } => {
+                sim.remove_emitter(*id);
+            }
+            InputEvent::SetEmitter { emitter } => {
+                if let Some(existing) = sim.emitter_mut(emitter.id) {
+                    let spawn_accumulator = existing.spawn_accumulator;
+                    *existing = emitter.clone();
+                    existing.spawn_accumulator = spawn_accumulator;
+                }
+            }
+            InputEvent::SetSettings { settings } => sim.set_settings(*settings),
+            InputEvent::Resize {
+                width,
+                height,
+                rescale,
+            } => sim.resize(*width, *height, *rescale),
+            InputEvent::Reseed { seed } => sim.reseed(*seed),
+            InputEvent::LoadScene { scene } => sim.load_scene(scene.clone()),
+            InputEvent::Restore { snapshot } => {
+                // Only snapshots that restored fine while recording get recorded
+                let _ = sim.restore(snapshot);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TimedEvent {
+    // Ticks since the recording started, the event happened after this many ticks had run
+    pub tick: u64,
+    pub event: InputEvent,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Recording {
+    pub version: u32,
+    pub initial_scene: Scene,
+    pub events: Vec<TimedEvent>,
+    // Ticks run by the time recording stopped
+    pub end_tick: u64,
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Malformed(serde_json::Error),
+    UnsupportedVersion(u32),
+    InvalidScene(SceneError),
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordingError::Malformed(err) => write!(f, "Malformed recording: {}", err),
+            RecordingError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported recording version {}, expected version {}",
+                version, RECORDING_VERSION
+            ),
+            RecordingError::InvalidScene(err) => write!(f, "Recording starts from a bad scene: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+// Just enough of a recording to check its version before parsing the rest
+#[derive(Deserialize)]
+struct RecordingHeader {
+    version: u32,
+}
+
+impl Recording {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("recordings always serialize")
+    }
+
+    pub fn from_json(json: &str) -> Result<Recording, RecordingError> {
+        let header: RecordingHeader = serde_json::from_str(json).map_err(RecordingError::Malformed)?;
+        if header.version != RECORDING_VERSION {
+            return Err(RecordingError::UnsupportedVersion(header.version));
+        }
+        let recording: Recording = serde_json::from_str(json).map_err(RecordingError::Malformed)?;
+        recording
+            .initial_scene
+            .validate()
+            .map_err(RecordingError::InvalidScene)?;
+        Ok(recording)
+    }
+
+    // Replays the whole recording into sim, leaving it in the state the
+    // recorded simulation was in when recording stopped
+    pub fn replay(&self, sim: &mut Simulation) {
+        let mut replay = Replay::start(self.clone(), sim);
+        while !replay.is_finished(sim) {
+            replay.apply_due_events(sim);
+            sim.tick(sim.get_fixed_timestep());
+        }
+        replay.apply_due_events(sim);
+    }
+}
+
+pub struct Recorder {
+    recording: Recording,
+    start_tick: u64,
+    last_settings: SimulationSettings,
+}
+
+impl Recorder {
+    // Starts recording sim. Its state is normalized to exactly what the
+    // recording's initial scene loads as, so the replay starts out identical
+    pub fn start(sim: &mut Simulation) -> Recorder {
+        let scene = sim.to_scene();
+        sim.load_scene(scene.clone());
+        Recorder {
+            recording: Recording {
+                version: RECORDING_VERSION,
+                initial_scene: scene,
+                events: Vec::new(),
+                end_tick: 0,
+            },
+            start_tick: sim.tick_count(),
+            last_settings: sim.settings(),
+        }
+    }
+
+    // Records an event that was just applied to sim
+    pub fn record(&mut self, sim: &Simulation, event: InputEvent) {
+        self.sync_settings(sim);
+        self.push(sim, event);
+        // Loading a scene or snapshot brings its own settings along
+        self.last_settings = sim.settings();
+    }
+
+    // Records the current settings if they changed since they were last recorded.
+    // Should be called before sim ticks so setting changes land on the right tick
+    pub fn sync_settings(&mut self, sim: &Simulation) {
+        let settings = sim.settings();
+        if settings != self.last_settings {
+            self.last_settings = settings;
+            self.push(sim, InputEvent::SetSettings { settings });
+        }
+    }
+
+    fn push(&mut self, sim: &Simulation, event: InputEvent) {
+        self.recording.events.push(TimedEvent {
+            tick: sim.tick_count() - self.start_tick,
+            event,
+        });
+    }
+
+    pub fn finish(mut self, sim: &Simulation) -> Recording {
+        self.sync_settings(sim);
+        self.recording.end_tick = sim.tick_count() - self.start_tick;
+        self.recording
+    }
+}
+
+// Plays a recording back into a simulation a tick at a time
+pub struct Replay {
+    recording: Recording,
+    start_tick: u64,
+    next_event: usize,
+    // Settings as the recording left them, changes made from outside are undone
+    settings: SimulationSettings,
+    // Size the simulation goes back to when the replay is stopped
+    live_size: (u32, u32),
+}
+
+impl Replay {
+    // Resets sim to the recording's initial scene, at the size it was recorded at
+    pub fn start(recording: Recording, sim: &mut Simulation) -> Replay {
+        let live_size = (sim.width(), sim.height());
+        let scene = recording.initial_scene.clone();
+        sim.set_size(scene.width, scene.height);
+        sim.load_scene(scene);
+        Replay {
+            recording,
+            start_tick: sim.tick_count(),
+            next_event: 0,
+            settings: sim.settings(),
+            live_size,
+        }
+    }
+
+    // Applies every event recorded at or before sim's current tick.
+    // Call this before each tick, e.g. from Simulation::step_with
+    pub fn apply_due_events(&mut self, sim: &mut Simulation) {
+        if sim.settings() != self.settings {
+            sim.set_settings(self.settings);
+        }
+        let tick = sim.tick_count() - self.start_tick;
+        while let Some(timed) = self.recording.events.get(self.next_event) {
+            if timed.tick > tick {
+                break;
+            }
+            timed.event.apply(sim);
+            self.next_event += 1;
+        }
+        self.settings = sim.settings();
+    }
+
+    // True once sim has run as many ticks as the recording did
+    pub fn is_finished(&self, sim: &Simulation) -> bool {
+        sim.tick_count() - self.start_tick >= self.recording.end_tick
+    }
+
+    // Changes the size stop() goes back to, for when the view is resized mid replay
+    pub fn set_live_size(&mut self, width: u32, height: u32) {
+        self.live_size = (width, height);
+    }
+
+    // Ends the replay, sim keeps its state but goes back to the size it had before
+    pub fn stop(self, sim: &mut Simulation) {
+        sim.set_size(self.live_size.0, self.live_size.1);
+    }
+}
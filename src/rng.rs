@@ -0,0 +1,102 @@
+// Random number generator the simulation draws from. It's PCG32 (XSH RR 64/32),
+// giving exactly the same numbers as rand_pcg's Pcg32 for the same seed, but with
+// its state out in the open so snapshots can save and restore it directly.
+
+use rand::{Error, RngCore, SeedableRng};
+
+const MULTIPLIER: u64 = 6364136223846793005;
+
+#[derive(Clone)]
+pub struct SimRng {
+    seed: u64,
+    state: u64,
+    // Always odd
+    increment: u64,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        SimRng {
+            seed,
+            ..SimRng::seed_from_u64(seed)
+        }
+    }
+
+    // Picks up a generator from the values returned by state(),
+    // returns None if the increment isn't odd like PCG needs
+    pub fn from_state(seed: u64, state: u64, increment: u64) -> Option<Self> {
+        if increment & 1 == 0 {
+            return None;
+        }
+        Some(SimRng {
+            seed,
+            state,
+            increment,
+        })
+    }
+
+    // Seed the generator was started from, see new
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Current state and increment of the underlying LCG
+    pub fn state(&self) -> (u64, u64) {
+        (self.state, self.increment)
+    }
+
+    fn step(&mut self) {
+        self.state = self.state.wrapping_mul(MULTIPLIER).wrapping_add(self.increment);
+    }
+}
+
+// Seeded the same way as Pcg32, seed_from_u64 comes from SeedableRng
+impl SeedableRng for SimRng {
+    type Seed = [u8; 16];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        let mut state = [0; 8];
+        let mut stream = [0; 8];
+        state.copy_from_slice(&seed[..8]);
+        stream.copy_from_slice(&seed[8..]);
+        let mut rng = SimRng {
+            seed: 0,
+            state: u64::from_le_bytes(state),
+            increment: u64::from_le_bytes(stream) | 1,
+        };
+        // Moves away from the initial value
+        rng.state = rng.state.wrapping_add(rng.increment);
+        rng.step();
+        rng
+    }
+}
+
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        let state = self.state;
+        self.step();
+        // xorshift the high bits down, then rotate by the top five
+        let rotate = (state >> 59) as u32;
+        let xorshifted = (((state >> 18) ^ state) >> 27) as u32;
+        xorshifted.rotate_right(rotate)
+    }
+
+    // Two draws, low half first, the same as Pcg32
+    fn next_u64(&mut self) -> u64 {
+        let low = self.next_u32() as u64;
+        let high = self.next_u32() as u64;
+        high << 32 | low
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
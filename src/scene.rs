@@ -0,0 +1,204 @@
+// Plain data description of a whole scene: every well, emitter and particle plus
+// the simulation and view settings. Scenes are saved to and loaded from JSON,
+// the version is bumped whenever the layout changes in a way older builds can't read.
+
+use crate::barnes_hut::NBodyConfig;
+use crate::border::Borders;
+use crate::collision::CollisionConfig;
+use crate::drag::DragConfig;
+use crate::emitter::Emitter;
+use crate::force_law::ForceConfig;
+use crate::gravity_well::GravityWell;
+use crate::integrator::Integrator;
+use crate::particle::Particle;
+use crate::speed_limit::SpeedLimit;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const SCENE_VERSION: u32 = 1;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Scene {
+    pub version: u32,
+    // Size of the area the scene was saved from, loading a scene keeps the current size
+    pub width: u32,
+    pub height: u32,
+    pub seed: u64,
+    pub settings: SimulationSettings,
+    pub gravity_wells: Vec<GravityWell>,
+    pub emitters: Vec<Emitter>,
+    pub particles: Vec<Particle>,
+    #[serde(default)]
+    pub view: ViewSettings,
+}
+
+// Every setting that affects how the simulation behaves
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SimulationSettings {
+    pub gravity_well_mass_multiplier: f64,
+    pub force: ForceConfig,
+    pub drag: DragConfig,
+    pub speed_limit: SpeedLimit,
+    pub nbody: NBodyConfig,
+    pub collisions: CollisionConfig,
+    pub borders: Borders,
+    // Lifetime given to newly spawned particles, null in JSON for forever
+    #[serde(with = "infinite_as_null")]
+    pub particle_lifetime: f64,
+    pub integrator: Integrator,
+    // Seconds per tick
+    pub fixed_timestep: f64,
+}
+
+impl SimulationSettings {
+    // Name of the first setting the simulation can't run with, if there is one
+    pub(crate) fn invalid_field(&self) -> Option<&'static str> {
+        let checks = [
+            ("gravity_well_mass_multiplier", self.gravity_well_mass_multiplier.is_finite()),
+            ("force.distance_scale", is_positive(self.force.distance_scale)),
+            ("force.softening", is_non_negative(self.force.softening)),
+            ("force.exponent", self.force.exponent.is_finite()),
+            ("drag.linear_coefficient", is_non_negative(self.drag.linear_coefficient)),
+            ("drag.quadratic_coefficient", is_non_negative(self.drag.quadratic_coefficient)),
+            ("speed_limit.max_speed", is_positive(self.speed_limit.max_speed)),
+            ("nbody.theta", is_non_negative(self.nbody.theta)),
+            ("nbody.strength", self.nbody.strength.is_finite()),
+            ("nbody.particle_mass", self.nbody.particle_mass.is_finite()),
+            ("nbody.softening", is_non_negative(self.nbody.softening)),
+            ("collisions.radius", is_non_negative(self.collisions.radius)),
+            ("collisions.separation_strength", self.collisions.separation_strength.is_finite()),
+            ("collisions.separation_radius", is_non_negative(self.collisions.separation_radius)),
+            ("borders.restitution", (0.0..=1.0).contains(&self.borders.restitution)),
+            // Infinite for particles that never expire
+            ("particle_lifetime", self.particle_lifetime > 0.0),
+            ("fixed_timestep", is_positive(self.fixed_timestep)),
+        ];
+        checks.iter().find(|(_, valid)| !valid).map(|(field, _)| *field)
+    }
+}
+
+// Settings that only change how the scene is drawn
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ViewSettings {
+    pub particle_trail_scale: f64,
+    pub should_clear_screen: bool,
+}
+
+impl Default for ViewSettings {
+    fn default() -> Self {
+        ViewSettings {
+            particle_trail_scale: 0.1,
+            should_clear_screen: true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    // Not valid JSON, or missing or mistyped fields
+    Malformed(serde_json::Error),
+    UnsupportedVersion(u32),
+    // Parsed fine but holds values the simulation can't run with
+    Invalid(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Malformed(err) => write!(f, "Malformed scene: {}", err),
+            SceneError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported scene version {}, expected version {}",
+                version, SCENE_VERSION
+            ),
+            SceneError::Invalid(reason) => write!(f, "Invalid scene: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+// Just enough of a scene to check its version before parsing the rest
+#[derive(Deserialize)]
+struct SceneHeader {
+    version: u32,
+}
+
+impl Scene {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("scenes always serialize")
+    }
+
+    pub fn from_json(json: &str) -> Result<Scene, SceneError> {
+        let header: SceneHeader = serde_json::from_str(json).map_err(SceneError::Malformed)?;
+        if header.version != SCENE_VERSION {
+            return Err(SceneError::UnsupportedVersion(header.version));
+        }
+        let scene: Scene = serde_json::from_str(json).map_err(SceneError::Malformed)?;
+        scene.validate()?;
+        Ok(scene)
+    }
+
+    // Checks every number that reaches the simulation, so a scene can't
+    // leave it stuck on NaNs or trying to spawn billions of particles
+    pub(crate) fn validate(&self) -> Result<(), SceneError> {
+        if let Some(field) = self.settings.invalid_field() {
+            return Err(SceneError::Invalid(format!("settings.{} is out of range", field)));
+        }
+        if !self.view.particle_trail_scale.is_finite() {
+            return Err(SceneError::Invalid(format!(
+                "view.particle_trail_scale must be finite, got {}",
+                self.view.particle_trail_scale
+            )));
+        }
+        for (i, particle) in self.particles.iter().enumerate() {
+            let motion_is_finite = particle.pos.iter().chain(&particle.vel).all(|v| v.is_finite());
+            if !(motion_is_finite && particle.age.is_finite() && !particle.lifetime.is_nan()) {
+                return Err(SceneError::Invalid(format!(
+                    "particle {} has a non-finite position, velocity, age or lifetime",
+                    i
+                )));
+            }
+        }
+        for (i, well) in self.gravity_wells.iter().enumerate() {
+            let values = [well.pos[0], well.pos[1], well.mass, well.rotation_deg];
+            if !values.iter().all(|v| v.is_finite()) {
+                return Err(SceneError::Invalid(format!(
+                    "gravity well {} has a non-finite position, mass or rotation",
+                    i
+                )));
+            }
+        }
+        for (i, emitter) in self.emitters.iter().enumerate() {
+            if let Some(field) = emitter.invalid_field() {
+                return Err(SceneError::Invalid(format!("emitter {} has an invalid {}", i, field)));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_positive(value: f64) -> bool {
+    value > 0.0 && value.is_finite()
+}
+
+fn is_non_negative(value: f64) -> bool {
+    value >= 0.0 && value.is_finite()
+}
+
+// JSON has no infinity, so lifetimes that never run out are stored as null
+pub(crate) mod infinite_as_null {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_infinite() {
+            serializer.serialize_none()
+        } else {
+            serializer.serialize_some(value)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        Ok(Option::<f64>::deserialize(deserializer)?.unwrap_or(f64::INFINITY))
+    }
+}
@@ -0,0 +1,943 @@
+// Renderer-independent particle simulation, owns the particles and gravity wells
+// and knows how to step the physics forward. Has no dependency on the browser,
+// so it can be run (and tested) natively as well as from the WasmApp wrapper.
+
+use crate::barnes_hut::{NBodyConfig, QuadTree};
+use crate::border::{BorderMode, Borders};
+use crate::collision::{self, CollisionConfig};
+use crate::color::Color;
+use crate::drag::DragConfig;
+use crate::emitter::{Emitter, EmitterShape};
+use crate::force_law::ForceConfig;
+use crate::gravity_well::{GravityWell, WellKind};
+use crate::history::{Edit, History};
+use crate::integrator::Integrator;
+use crate::particle::{Particle, Particles};
+use crate::rng::SimRng;
+use crate::scene::{Scene, SimulationSettings, ViewSettings, SCENE_VERSION};
+#[cfg(feature = "simd")]
+use crate::simd;
+use crate::snapshot::{self, SnapshotError, SnapshotReader, SnapshotWriter};
+use crate::spatial_grid::SpatialGrid;
+use crate::speed_limit::SpeedLimit;
+use rand::Rng;
+
+// Well forces were originally tuned as velocity kicks applied once per 60 Hz tick,
+// this turns them into accelerations in pixels per second squared
+pub(crate) const FORCE_SCALE: f64 = 60.0;
+
+// Most time a single step will simulate, anything past it is dropped. Keeps a long
+// pause (like a backgrounded tab) from running thousands of ticks in one frame,
+// which would make the next frame late too and so on
+pub const MAX_STEP_SECONDS: f64 = 0.25;
+
+pub struct Simulation {
+    width: u32,
+    height: u32,
+    particles: Particles,
+    // Scratch space for accumulating accelerations, kept around between ticks
+    acc_x: Vec<f64>,
+    acc_y: Vec<f64>,
+    gravity_wells: Vec<GravityWell>,
+    emitters: Vec<Emitter>,
+    next_emitter_id: u32,
+    gravity_well_mass_multiplier: f64,
+    force_config: ForceConfig,
+    drag_config: DragConfig,
+    speed_limit: SpeedLimit,
+    nbody_config: NBodyConfig,
+    collision_config: CollisionConfig,
+    grid: SpatialGrid,
+    borders: Borders,
+    particle_lifetime: f64,
+    integrator: Integrator,
+    fixed_timestep: f64,
+    time_accumulator: f64,
+    // Ticks run since the simulation was created
+    tick_count: u64,
+    rng: SimRng,
+    history: History,
+    #[cfg(feature = "simd")]
+    simd_enabled: bool,
+    #[cfg(feature = "parallel")]
+    parallel_enabled: bool,
+}
+
+impl Simulation {
+    // Creates a simulation with a randomly chosen seed,
+    // use seed() to find out which one was picked
+    pub fn new(width: u32, height: u32) -> Self {
+        Simulation::with_seed(width, height, rand::random())
+    }
+
+    // Creates a simulation whose random particle layouts and colors
+    // are fully determined by the given seed
+    pub fn with_seed(width: u32, height: u32, seed: u64) -> Self {
+        Simulation {
+            width,
+            height,
+            particles: Particles::new(),
+            acc_x: Vec::new(),
+            acc_y: Vec::new(),
+            gravity_wells: Vec::new(),
+            emitters: Vec::new(),
+            next_emitter_id: 0,
+            gravity_well_mass_multiplier: 1.0,
+            force_config: ForceConfig::default(),
+            drag_config: DragConfig::default(),
+            speed_limit: SpeedLimit::default(),
+            nbody_config: NBodyConfig::default(),
+            collision_config: CollisionConfig::default(),
+            grid: SpatialGrid::new(CollisionConfig::default().cell_size()),
+            borders: Borders::default(),
+            particle_lifetime: f64::INFINITY,
+            integrator: Integrator::SemiImplicitEuler,
+            fixed_timestep: 1.0 / 60.0,
+            time_accumulator: 0.0,
+            tick_count: 0,
+            rng: SimRng::new(seed),
+            history: History::new(),
+            #[cfg(feature = "simd")]
+            simd_enabled: true,
+            #[cfg(feature = "parallel")]
+            parallel_enabled: true,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.rng.seed()
+    }
+
+    // Restarts the random number sequence from the given seed
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = SimRng::new(seed);
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+    }
+
+    // Changes the size like set_size, optionally moving every particle, well and emitter
+    // so they keep their place relative to the edges. The undo history is scaled along
+    // with them, so undoing a well edit puts it back where it is at the new size
+    pub fn resize(&mut self, width: u32, height: u32, rescale_positions: bool) {
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+        if rescale_positions && self.width > 0 && self.height > 0 {
+            let scale_x = width as f64 / self.width as f64;
+            let scale_y = height as f64 / self.height as f64;
+            let motion = self.particles.motion_mut();
+            for x in motion.pos_x {
+                *x = (*x as f64 * scale_x) as f32;
+            }
+            for y in motion.pos_y {
+                *y = (*y as f64 * scale_y) as f32;
+            }
+            for well in &mut self.gravity_wells {
+                well.pos = [well.pos[0] * scale_x, well.pos[1] * scale_y];
+            }
+            for emitter in &mut self.emitters {
+                emitter.pos = [emitter.pos[0] * scale_x, emitter.pos[1] * scale_y];
+                emitter.size = [emitter.size[0] * scale_x, emitter.size[1] * scale_y];
+            }
+            self.history.scale_positions(scale_x, scale_y);
+        }
+        self.set_size(width, height);
+    }
+
+    pub fn particles(&self) -> &Particles {
+        &self.particles
+    }
+
+    pub fn gravity_wells(&self) -> &[GravityWell] {
+        &self.gravity_wells
+    }
+
+    pub fn emitters(&self) -> &[Emitter] {
+        &self.emitters
+    }
+
+    pub fn initialize_particles(&mut self, num_particles: u32) {
+        let min_vel = -80.0;
+        let max_vel = 80.0;
+        for _ in 0..num_particles {
+            let pos_x = self.rng.gen::<f64>() * self.width as f64;
+            let pos_y = self.rng.gen::<f64>() * self.height as f64;
+            let vel_x = self.rng.gen::<f64>() * (max_vel - min_vel) + min_vel;
+            let vel_y = self.rng.gen::<f64>() * (max_vel - min_vel) + min_vel;
+            self.spawn_particle(pos_x, pos_y, vel_x, vel_y);
+        }
+    }
+
+    // Advance the simulation by delta seconds, in as many fixed size ticks as fit.
+    // Leftover time is carried over to the next call, so results don't depend
+    // on how the caller splits up time. Delta is capped at MAX_STEP_SECONDS,
+    // or one tick if ticks are longer than that
+    pub fn step(&mut self, delta: f64) {
+        self.step_with(delta, |_| {});
+    }
+
+    // Same as step, but calls before_tick ahead of every tick,
+    // for things that need to happen on an exact tick like replaying input
+    pub fn step_with<F>(&mut self, delta: f64, mut before_tick: F)
+    where
+        F: FnMut(&mut Simulation),
+    {
+        self.time_accumulator += delta.min(MAX_STEP_SECONDS.max(self.fixed_timestep));
+        while self.time_accumulator >= self.fixed_timestep {
+            self.time_accumulator -= self.fixed_timestep;
+            before_tick(self);
+            self.tick(self.fixed_timestep);
+        }
+    }
+
+    pub fn tick_count(&self) -> u64 {
+        self.tick_count
+    }
+
+    // Advance the simulation by exactly dt seconds
+    pub fn tick(&mut self, dt: f64) {
+        self.tick_count += 1;
+        for well in &mut self.gravity_wells {
+            well.rotation_deg += GravityWell::ROTATION_SPEED * dt;
+            well.rotation_deg %= 360.0;
+        }
+
+        for emitter in &mut self.emitters {
+            for _ in 0..emitter.advance(dt) {
+                self.particles.push(&emitter.emit(&mut self.rng));
+            }
+        }
+
+        // Particles attract each other based on where they were at the start of the tick
+        let quadtree = if self.nbody_config.enabled {
+            Some(QuadTree::build(
+                self.particles.positions(),
+                self.nbody_config.particle_mass,
+            ))
+        } else {
+            None
+        };
+
+        let forces = Forces {
+            gravity_wells: &self.gravity_wells,
+            mass_multiplier: self.gravity_well_mass_multiplier,
+            force_config: &self.force_config,
+            drag_config: &self.drag_config,
+            nbody_config: &self.nbody_config,
+            quadtree: quadtree.as_ref(),
+            #[cfg(feature = "simd")]
+            simd_enabled: self.simd_enabled,
+        };
+        let integrator = self.integrator;
+        #[cfg(feature = "parallel")]
+        let parallel = self.parallel_enabled;
+        #[cfg(not(feature = "parallel"))]
+        let parallel = false;
+        for_each_chunk(
+            &mut self.particles,
+            &mut self.acc_x,
+            &mut self.acc_y,
+            parallel,
+            |mut chunk| match integrator {
+                Integrator::SemiImplicitEuler => forces.integrate_in_passes(&mut chunk, dt),
+                integrator => forces.integrate_per_particle(integrator, &mut chunk, dt),
+            },
+        );
+
+        let speed_limit = &self.speed_limit;
+        let borders = &self.borders;
+        let (width, height) = (self.width as f64, self.height as f64);
+        self.particles.age_and_retain(dt, |pos, vel| {
+            *vel = speed_limit.apply(*vel);
+            borders.apply(pos, vel, width, height)
+        });
+
+        if self.collision_config.is_active() {
+            self.rebuild_grid();
+            if self.collision_config.collisions_enabled {
+                collision::resolve_collisions(&mut self.particles, &self.grid, &self.collision_config);
+            }
+            if self.collision_config.separation_strength != 0.0 {
+                collision::apply_separation(&mut self.particles, &self.grid, &self.collision_config, dt);
+            }
+        }
+    }
+
+    pub fn settings(&self) -> SimulationSettings {
+        SimulationSettings {
+            gravity_well_mass_multiplier: self.gravity_well_mass_multiplier,
+            force: self.force_config,
+            drag: self.drag_config,
+            speed_limit: self.speed_limit,
+            nbody: self.nbody_config,
+            collisions: self.collision_config,
+            borders: self.borders,
+            particle_lifetime: self.particle_lifetime,
+            integrator: self.integrator,
+            fixed_timestep: self.fixed_timestep,
+        }
+    }
+
+    pub fn set_settings(&mut self, settings: SimulationSettings) {
+        self.gravity_well_mass_multiplier = settings.gravity_well_mass_multiplier;
+        self.force_config = settings.force;
+        self.drag_config = settings.drag;
+        self.speed_limit = settings.speed_limit;
+        self.nbody_config = settings.nbody;
+        self.collision_config = settings.collisions;
+        self.borders = settings.borders;
+        self.particle_lifetime = settings.particle_lifetime;
+        self.integrator = settings.integrator;
+        self.fixed_timestep = settings.fixed_timestep;
+    }
+
+    // Snapshot of the current scene, view settings are left at their defaults
+    pub fn to_scene(&self) -> Scene {
+        Scene {
+            version: SCENE_VERSION,
+            width: self.width,
+            height: self.height,
+            seed: self.seed(),
+            settings: self.settings(),
+            gravity_wells: self
+                .gravity_wells
+                .iter()
+                .map(|well| GravityWell {
+                    rotation_deg: well.rotation_deg,
+                    ..GravityWell::new(well.pos, well.mass, well.kind)
+                })
+                .collect(),
+            emitters: self.emitters.clone(),
+            particles: self.particles.iter().collect(),
+            view: ViewSettings::default(),
+        }
+    }
+
+    // Replaces every well, emitter, particle and setting with the ones in the scene
+    // and restarts the random sequence from its seed. The size is left alone,
+    // it belongs to whatever the simulation is being displayed on
+    pub fn load_scene(&mut self, scene: Scene) {
+        self.set_settings(scene.settings);
+        self.gravity_wells = scene.gravity_wells;
+        self.next_emitter_id = scene.emitters.iter().map(|e| e.id + 1).max().unwrap_or(0);
+        self.emitters = scene.emitters;
+        self.particles.clear();
+        for particle in &scene.particles {
+            self.particles.push(particle);
+        }
+        self.time_accumulator = 0.0;
+        self.reseed(scene.seed);
+        self.history.clear();
+    }
+
+    // Compact binary copy of the entire simulation state, including the random
+    // number generator, so restoring it continues exactly where it left off
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut writer = SnapshotWriter::new(snapshot::estimated_size(self.particles.len()));
+        writer.write_u32(self.width);
+        writer.write_u32(self.height);
+        snapshot::write_rng(&mut writer, &self.rng);
+        writer.write_f64(self.time_accumulator);
+        snapshot::write_settings(&mut writer, &self.settings());
+        snapshot::write_wells(&mut writer, &self.gravity_wells);
+        writer.write_u32(self.next_emitter_id);
+        snapshot::write_emitters(&mut writer, &self.emitters);
+        snapshot::write_particles(&mut writer, &self.particles);
+        writer.finish()
+    }
+
+    // Replaces the entire simulation state with one from snapshot(),
+    // on error the current state is left untouched
+    pub fn restore(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = SnapshotReader::new(bytes)?;
+        let width = reader.read_u32("width")?;
+        let height = reader.read_u32("height")?;
+        let rng = snapshot::read_rng(&mut reader)?;
+        let time_accumulator = reader.read_finite_f64("time accumulator")?;
+        if time_accumulator < 0.0 {
+            return Err(SnapshotError::InvalidValue("time accumulator"));
+        }
+        let settings = snapshot::read_settings(&mut reader)?;
+        let gravity_wells = snapshot::read_wells(&mut reader)?;
+        let next_emitter_id = reader.read_u32("next emitter id")?;
+        let emitters = snapshot::read_emitters(&mut reader)?;
+        let particles = snapshot::read_particles(&mut reader)?;
+        reader.finish()?;
+
+        self.width = width;
+        self.height = height;
+        self.rng = rng;
+        self.time_accumulator = time_accumulator;
+        self.set_settings(settings);
+        self.gravity_wells = gravity_wells;
+        self.next_emitter_id = next_emitter_id;
+        self.emitters = emitters;
+        self.particles = particles;
+        self.history.clear();
+        Ok(())
+    }
+
+    fn rebuild_grid(&mut self) {
+        self.grid.set_cell_size(self.collision_config.cell_size());
+        self.grid.rebuild(self.particles.positions());
+    }
+
+    // Indices (oldest first order) of every particle within radius of (x, y)
+    pub fn particles_within(&mut self, x: f64, y: f64, radius: f64) -> Vec<usize> {
+        self.rebuild_grid();
+        let mut found = self.grid.query_radius([x, y], radius);
+        found.sort_unstable();
+        found
+    }
+
+    pub fn spawn_particle(&mut self, x: f64, y: f64, vel_x: f64, vel_y: f64) {
+        let color = Color::random(&mut self.rng);
+        let mut particle = Particle::new(x, y, vel_x, vel_y, color);
+        particle.lifetime = self.particle_lifetime;
+        self.particles.push(&particle);
+    }
+
+    pub fn spawn_gravity_well(&mut self, x: f64, y: f64) {
+        self.spawn_gravity_well_of_kind(x, y, WellKind::Attractor);
+    }
+
+    pub fn spawn_gravity_well_of_kind(&mut self, x: f64, y: f64, kind: WellKind) {
+        let well = GravityWell::new([x, y], GravityWell::DEFAULT_MASS, kind);
+        self.history.record(Edit::AddWell {
+            index: self.gravity_wells.len(),
+            well: well.clone(),
+        });
+        self.gravity_wells.push(well);
+    }
+
+    pub fn selected_well_index(&self) -> Option<usize> {
+        self.gravity_wells.iter().position(|well| well.is_selected)
+    }
+
+    pub fn get_well_mass(&self, index: usize) -> Option<f64> {
+        self.gravity_wells.get(index).map(|well| well.mass)
+    }
+
+    // Returns false if there is no well at the given index
+    pub fn set_well_mass(&mut self, index: usize, mass: f64) -> bool {
+        match self.gravity_wells.get_mut(index) {
+            Some(well) => {
+                if well.mass != mass {
+                    self.history.record(Edit::SetWellMass {
+                        index,
+                        from: well.mass,
+                        to: mass,
+                    });
+                    well.mass = mass;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Adds an emitter with default settings and returns its id
+    pub fn add_emitter(&mut self, shape: EmitterShape, x: f64, y: f64) -> u32 {
+        let id = self.next_emitter_id;
+        self.next_emitter_id += 1;
+        self.emitters.push(Emitter::new(id, shape, [x, y]));
+        id
+    }
+
+    pub fn emitter_mut(&mut self, id: u32) -> Option<&mut Emitter> {
+        self.emitters.iter_mut().find(|emitter| emitter.id == id)
+    }
+
+    // Returns false if there is no emitter with the given id
+    pub fn remove_emitter(&mut self, id: u32) -> bool {
+        let count_before = self.emitters.len();
+        self.emitters.retain(|emitter| emitter.id != id);
+        self.emitters.len() != count_before
+    }
+
+    pub fn try_selecting(&mut self, x: f64, y: f64) -> bool {
+        let mut found = false;
+        for well in self.gravity_wells.iter_mut().rev() {
+            if well.is_point_inside(x, y) && !found {
+                well.is_selected = true;
+                found = true;
+            } else {
+                well.is_selected = false;
+            }
+        }
+        found
+    }
+
+    // Also ends the current drag, so the next move starts a new undo entry
+    pub fn release_selection(&mut self) {
+        for well in &mut self.gravity_wells {
+            well.is_selected = false;
+        }
+        self.history.end_drag();
+    }
+
+    // Moves made between selecting a well and releasing it are undone as one
+    pub fn move_selection_by(&mut self, x: f64, y: f64) {
+        if x == 0.0 && y == 0.0 {
+            return;
+        }
+        for (index, well) in self.gravity_wells.iter_mut().enumerate() {
+            if well.is_selected {
+                let from = well.pos;
+                well.move_by(x, y);
+                self.history.record(Edit::MoveWell {
+                    index,
+                    from,
+                    to: well.pos,
+                });
+            }
+        }
+    }
+
+    pub fn try_removing(&mut self, x: f64, y: f64) {
+        if let Some(index) = self
+            .gravity_wells
+            .iter()
+            .position(|well| well.is_point_inside(x, y))
+        {
+            let well = self.gravity_wells.remove(index);
+            self.history.record(Edit::RemoveWell { index, well });
+        }
+    }
+
+    // Reverts the most recent well edit, returns false if there was nothing to undo
+    pub fn undo(&mut self) -> bool {
+        match self.history.undo() {
+            Some(edit) => {
+                let edit = edit.clone();
+                self.apply_edit(&edit, true);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Reapplies the most recently undone well edit, returns false if there was nothing to redo
+    pub fn redo(&mut self) -> bool {
+        match self.history.redo() {
+            Some(edit) => {
+                let edit = edit.clone();
+                self.apply_edit(&edit, false);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    // Applies an edit, or its opposite when reverting, without recording it
+    fn apply_edit(&mut self, edit: &Edit, revert: bool) {
+        match edit {
+            Edit::AddWell { index, well } | Edit::RemoveWell { index, well } => {
+                let adding = matches!(edit, Edit::AddWell { .. }) != revert;
+                if adding {
+                    let mut well = well.clone();
+                    well.is_selected = false;
+                    self.gravity_wells.insert(*index, well);
+                } else {
+                    self.gravity_wells.remove(*index);
+                }
+            }
+            Edit::MoveWell { index, from, to } => {
+                self.gravity_wells[*index].pos = if revert { *from } else { *to };
+            }
+            Edit::SetWellMass { index, from, to } => {
+                self.gravity_wells[*index].mass = if revert { *from } else { *to };
+            }
+        }
+    }
+
+    pub fn clear_particles(&mut self) {
+        self.particles.clear();
+    }
+
+    // Removes the oldest num_to_remove particles
+    pub fn remove_particles(&mut self, num_to_remove: usize) {
+        self.particles.remove_oldest(num_to_remove);
+    }
+
+    // Global scale applied on top of every well's own mass
+    pub fn set_gravity_well_mass_multiplier(&mut self, multiplier: f64) {
+        self.gravity_well_mass_multiplier = multiplier;
+    }
+
+    pub fn get_gravity_well_mass_multiplier(&self) -> f64 {
+        self.gravity_well_mass_multiplier
+    }
+
+    pub fn force_config(&self) -> ForceConfig {
+        self.force_config
+    }
+
+    pub fn set_force_config(&mut self, config: ForceConfig) {
+        self.force_config = config;
+    }
+
+    pub fn drag_config(&self) -> DragConfig {
+        self.drag_config
+    }
+
+    pub fn set_drag_config(&mut self, config: DragConfig) {
+        self.drag_config = config;
+    }
+
+    pub fn speed_limit(&self) -> SpeedLimit {
+        self.speed_limit
+    }
+
+    pub fn set_speed_limit(&mut self, speed_limit: SpeedLimit) {
+        self.speed_limit = speed_limit;
+    }
+
+    pub fn nbody_config(&self) -> NBodyConfig {
+        self.nbody_config
+    }
+
+    pub fn set_nbody_config(&mut self, config: NBodyConfig) {
+        self.nbody_config = config;
+    }
+
+    pub fn collision_config(&self) -> CollisionConfig {
+        self.collision_config
+    }
+
+    pub fn set_collision_config(&mut self, config: CollisionConfig) {
+        self.collision_config = config;
+    }
+
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        self.integrator = integrator;
+    }
+
+    pub fn get_integrator(&self) -> Integrator {
+        self.integrator
+    }
+
+    // Sets the length in seconds of a single simulation tick,
+    // non-positive lengths are ignored
+    pub fn set_fixed_timestep(&mut self, seconds: f64) {
+        if seconds > 0.0 {
+            self.fixed_timestep = seconds;
+        }
+    }
+
+    pub fn get_fixed_timestep(&self) -> f64 {
+        self.fixed_timestep
+    }
+
+    // Lifetime in seconds given to newly spawned particles,
+    // zero or less means they live forever
+    pub fn set_particle_lifetime(&mut self, seconds: f64) {
+        self.particle_lifetime = if seconds > 0.0 { seconds } else { f64::INFINITY };
+    }
+
+    pub fn get_particle_lifetime(&self) -> f64 {
+        self.particle_lifetime
+    }
+
+    pub fn borders(&self) -> Borders {
+        self.borders
+    }
+
+    pub fn set_borders(&mut self, borders: Borders) {
+        self.borders = borders;
+    }
+
+    pub fn set_border_mode(&mut self, mode: BorderMode) {
+        self.borders.mode = mode;
+    }
+
+    // Switches the semi-implicit Euler passes between the SIMD and scalar versions,
+    // the results agree to within float rounding
+    #[cfg(feature = "simd")]
+    pub fn set_simd_enabled(&mut self, enabled: bool) {
+        self.simd_enabled = enabled;
+    }
+
+    #[cfg(feature = "simd")]
+    pub fn is_simd_enabled(&self) -> bool {
+        self.simd_enabled
+    }
+
+    // Switches between splitting particle integration across rayon's thread pool
+    // and running it on the calling thread, both give identical results
+    #[cfg(feature = "parallel")]
+    pub fn set_parallel_enabled(&mut self, enabled: bool) {
+        self.parallel_enabled = enabled;
+    }
+
+    #[cfg(feature = "parallel")]
+    pub fn is_parallel_enabled(&self) -> bool {
+        self.parallel_enabled
+    }
+}
+
+// Mutable view over a run of consecutive particles and their acceleration scratch space.
+// Lets the integration passes run over the whole particle array at once,
+// or over separate chunks of it on different threads
+pub(crate) struct ParticleChunk<'a> {
+    pub pos_x: &'a mut [f32],
+    pub pos_y: &'a mut [f32],
+    pub vel_x: &'a mut [f32],
+    pub vel_y: &'a mut [f32],
+    pub acc_x: &'a mut [f64],
+    pub acc_y: &'a mut [f64],
+}
+
+impl ParticleChunk<'_> {
+    pub fn len(&self) -> usize {
+        self.pos_x.len()
+    }
+}
+
+// Number of particles handed to a thread at a time with parallel updates.
+// A multiple of four, so the SIMD passes never leave a scalar tail mid-array
+#[cfg(feature = "parallel")]
+const PARALLEL_CHUNK_SIZE: usize = 1024;
+
+// Zeroes the acceleration scratch space, then calls integrate on chunks of the particles
+// spread across rayon's thread pool, or once with every particle when parallel is false
+#[cfg(feature = "parallel")]
+fn for_each_chunk<F>(
+    particles: &mut Particles,
+    acc_x: &mut Vec<f64>,
+    acc_y: &mut Vec<f64>,
+    parallel: bool,
+    integrate: F,
+) where
+    F: Fn(ParticleChunk) + Sync + Send,
+{
+    use rayon::prelude::*;
+
+    if !parallel {
+        return for_each_chunk_serial(particles, acc_x, acc_y, integrate);
+    }
+    reset_scratch(particles.len(), acc_x, acc_y);
+    let size = PARALLEL_CHUNK_SIZE;
+    let motion = particles.motion_mut();
+    (
+        motion.pos_x.par_chunks_mut(size),
+        motion.pos_y.par_chunks_mut(size),
+        motion.vel_x.par_chunks_mut(size),
+        motion.vel_y.par_chunks_mut(size),
+        acc_x.par_chunks_mut(size),
+        acc_y.par_chunks_mut(size),
+    )
+        .into_par_iter()
+        .for_each(|(pos_x, pos_y, vel_x, vel_y, acc_x, acc_y)| {
+            integrate(ParticleChunk {
+                pos_x,
+                pos_y,
+                vel_x,
+                vel_y,
+                acc_x,
+                acc_y,
+            })
+        });
+}
+
+#[cfg(not(feature = "parallel"))]
+fn for_each_chunk<F>(
+    particles: &mut Particles,
+    acc_x: &mut Vec<f64>,
+    acc_y: &mut Vec<f64>,
+    _parallel: bool,
+    integrate: F,
+) where
+    F: Fn(ParticleChunk),
+{
+    for_each_chunk_serial(particles, acc_x, acc_y, integrate);
+}
+
+fn for_each_chunk_serial<F>(particles: &mut Particles, acc_x: &mut Vec<f64>, acc_y: &mut Vec<f64>, integrate: F)
+where
+    F: Fn(ParticleChunk),
+{
+    reset_scratch(particles.len(), acc_x, acc_y);
+    let motion = particles.motion_mut();
+    integrate(ParticleChunk {
+        pos_x: motion.pos_x,
+        pos_y: motion.pos_y,
+        vel_x: motion.vel_x,
+        vel_y: motion.vel_y,
+        acc_x,
+        acc_y,
+    });
+}
+
+fn reset_scratch(len: usize, acc_x: &mut Vec<f64>, acc_y: &mut Vec<f64>) {
+    acc_x.clear();
+    acc_x.resize(len, 0.0);
+    acc_y.clear();
+    acc_y.resize(len, 0.0);
+}
+
+// Everything that pushes particles around during a tick, borrowed from the simulation
+// so it can be shared between threads while the particle arrays are being written
+struct Forces<'a> {
+    gravity_wells: &'a [GravityWell],
+    mass_multiplier: f64,
+    force_config: &'a ForceConfig,
+    drag_config: &'a DragConfig,
+    nbody_config: &'a NBodyConfig,
+    // Built from the particle positions at the start of the tick when N-body is on
+    quadtree: Option<&'a QuadTree>,
+    #[cfg(feature = "simd")]
+    simd_enabled: bool,
+}
+
+impl Forces<'_> {
+    // Acceleration on a particle at pos moving with vel
+    fn acceleration(&self, pos: [f64; 2], vel: [f64; 2]) -> [f64; 2] {
+        let mut acc = vecmath::vec2_add(
+            well_acceleration(self.gravity_wells, self.mass_multiplier, self.force_config, pos),
+            self.drag_config.acceleration(vel),
+        );
+        if let Some(quadtree) = self.quadtree {
+            acc = vecmath::vec2_add(acc, quadtree.acceleration(pos, self.nbody_config));
+        }
+        acc
+    }
+
+    // Semi-implicit Euler split into passes over the particle arrays:
+    // one pass per well accumulating acceleration, then one pass applying
+    // drag and moving every particle
+    fn integrate_in_passes(&self, chunk: &mut ParticleChunk, dt: f64) {
+        let len = chunk.len();
+        for well in self.gravity_wells {
+            let mass = well.mass * self.mass_multiplier;
+            #[cfg(feature = "simd")]
+            let done = if self.simd_enabled {
+                simd::accumulate_well_acceleration(well, mass, self.force_config, chunk)
+            } else {
+                0
+            };
+            #[cfg(not(feature = "simd"))]
+            let done = 0;
+            accumulate_well_acceleration(
+                well,
+                mass,
+                self.force_config,
+                &chunk.pos_x[done..],
+                &chunk.pos_y[done..],
+                &mut chunk.acc_x[done..],
+                &mut chunk.acc_y[done..],
+            );
+        }
+
+        if let Some(quadtree) = self.quadtree {
+            for i in 0..len {
+                let pos = [chunk.pos_x[i] as f64, chunk.pos_y[i] as f64];
+                let acc = quadtree.acceleration(pos, self.nbody_config);
+                chunk.acc_x[i] += acc[0];
+                chunk.acc_y[i] += acc[1];
+            }
+        }
+
+        #[cfg(feature = "simd")]
+        let done = if self.simd_enabled {
+            simd::integrate_positions(chunk, self.drag_config, dt)
+        } else {
+            0
+        };
+        #[cfg(not(feature = "simd"))]
+        let done = 0;
+        for i in done..len {
+            let vel = [chunk.vel_x[i] as f64, chunk.vel_y[i] as f64];
+            let drag = self.drag_config.acceleration(vel);
+            let vel_x = vel[0] + (chunk.acc_x[i] + drag[0]) * dt;
+            let vel_y = vel[1] + (chunk.acc_y[i] + drag[1]) * dt;
+            chunk.vel_x[i] = vel_x as f32;
+            chunk.vel_y[i] = vel_y as f32;
+            chunk.pos_x[i] = (chunk.pos_x[i] as f64 + vel_x * dt) as f32;
+            chunk.pos_y[i] = (chunk.pos_y[i] as f64 + vel_y * dt) as f32;
+        }
+    }
+
+    // Integrates each particle on its own, for schemes that need to
+    // evaluate the acceleration at intermediate positions
+    fn integrate_per_particle(&self, integrator: Integrator, chunk: &mut ParticleChunk, dt: f64) {
+        for i in 0..chunk.len() {
+            let mut pos = [chunk.pos_x[i] as f64, chunk.pos_y[i] as f64];
+            let mut vel = [chunk.vel_x[i] as f64, chunk.vel_y[i] as f64];
+            integrator.step(&mut pos, &mut vel, dt, |pos, vel| self.acceleration(pos, vel));
+            chunk.pos_x[i] = pos[0] as f32;
+            chunk.pos_y[i] = pos[1] as f32;
+            chunk.vel_x[i] = vel[0] as f32;
+            chunk.vel_y[i] = vel[1] as f32;
+        }
+    }
+}
+
+// Sums the acceleration every gravity well applies to a particle at pos
+fn well_acceleration(
+    gravity_wells: &[GravityWell],
+    mass_multiplier: f64,
+    force_config: &ForceConfig,
+    pos: [f64; 2],
+) -> [f64; 2] {
+    let mut total = [0.0, 0.0];
+    for well in gravity_wells {
+        let acc = single_well_acceleration(well, well.mass * mass_multiplier, force_config, pos);
+        total = vecmath::vec2_add(total, acc);
+    }
+    total
+}
+
+fn single_well_acceleration(
+    well: &GravityWell,
+    mass: f64,
+    force_config: &ForceConfig,
+    pos: [f64; 2],
+) -> [f64; 2] {
+    let p_to_well = vecmath::vec2_sub(well.pos, pos);
+    let distance = vecmath::vec2_len(p_to_well);
+    if distance == 0.0 {
+        return [0.0, 0.0];
+    }
+    let grav_force = FORCE_SCALE * force_config.magnitude(mass, distance);
+    let force_dir = vecmath::vec2_scale(p_to_well, 1.0 / distance);
+    well.acceleration(force_dir, grav_force)
+}
+
+// Adds the acceleration a single well of the given mass applies to every particle into acc_x and acc_y
+fn accumulate_well_acceleration(
+    well: &GravityWell,
+    mass: f64,
+    force_config: &ForceConfig,
+    pos_x: &[f32],
+    pos_y: &[f32],
+    acc_x: &mut [f64],
+    acc_y: &mut [f64],
+) {
+    let positions = pos_x.iter().zip(pos_y);
+    let accelerations = acc_x.iter_mut().zip(acc_y.iter_mut());
+    for ((x, y), (ax, ay)) in positions.zip(accelerations) {
+        let acc = single_well_acceleration(well, mass, force_config, [*x as f64, *y as f64]);
+        *ax += acc[0];
+        *ay += acc[1];
+    }
+}
@@ -0,0 +1,494 @@
+// Compact binary snapshots of the full simulation state, small and fast enough
+// to take every frame for rewinding, checkpointing or sending to another worker.
+// Everything is little-endian. A snapshot is laid out as:
+//
+//   magic "PSNP", version u32
+//   width u32, height u32, seed u64, rng state u64, rng increment u64,
+//   time accumulator f64
+//   settings (see write_settings)
+//   well count u32, then per well: x f64, y f64, rotation f64, mass f64, kind u8, selected u8
+//   next emitter id u32, emitter count u32, then per emitter: see write_emitters
+//   particle count u32, then one array per attribute with an entry per particle:
+//     pos_x f32, pos_y f32, vel_x f32, vel_y f32, color RRGGBBAA u32, age f32, lifetime f32
+//
+// Enums are stored as their u8 discriminant and bools as 0 or 1.
+
+use crate::barnes_hut::NBodyConfig;
+use crate::border::{BorderMode, Borders};
+use crate::collision::CollisionConfig;
+use crate::color::Color;
+use crate::drag::{DragConfig, DragModel};
+use crate::emitter::{Emitter, EmitterShape};
+use crate::force_law::{ForceConfig, ForceLaw};
+use crate::gravity_well::{GravityWell, WellKind};
+use crate::integrator::Integrator;
+use crate::particle::Particles;
+use crate::scene::SimulationSettings;
+use crate::speed_limit::{SpeedLimit, SpeedLimitMode};
+use crate::rng::SimRng;
+use std::fmt;
+
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"PSNP";
+pub const SNAPSHOT_VERSION: u32 = 4;
+
+// Bytes each particle takes up in the attribute arrays
+const BYTES_PER_PARTICLE: usize = 7 * 4;
+
+#[derive(Debug, PartialEq)]
+pub enum SnapshotError {
+    BadMagic,
+    UnsupportedVersion(u32),
+    // Ran out of bytes while reading the named field
+    Truncated(&'static str),
+    // A field held a value that doesn't mean anything, like an unknown enum discriminant
+    InvalidValue(&'static str),
+    // Bytes left over after the end of the snapshot
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::BadMagic => write!(f, "Not a particle snapshot"),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported snapshot version {}, expected version {}",
+                version, SNAPSHOT_VERSION
+            ),
+            SnapshotError::Truncated(field) => write!(f, "Snapshot ends in the middle of {}", field),
+            SnapshotError::InvalidValue(field) => write!(f, "Snapshot has an invalid {}", field),
+            SnapshotError::TrailingBytes(count) => {
+                write!(f, "Snapshot has {} unexpected bytes at the end", count)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+pub struct SnapshotWriter {
+    bytes: Vec<u8>,
+}
+
+impl SnapshotWriter {
+    // Starts a snapshot with the magic and version already written
+    pub fn new(capacity: usize) -> Self {
+        let mut writer = SnapshotWriter {
+            bytes: Vec::with_capacity(capacity),
+        };
+        writer.bytes.extend_from_slice(&SNAPSHOT_MAGIC);
+        writer.write_u32(SNAPSHOT_VERSION);
+        writer
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f64(&mut self, value: f64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_len(&mut self, len: usize) {
+        self.write_u32(len as u32);
+    }
+
+    pub fn write_f32s(&mut self, values: &[f32]) {
+        for value in values {
+            self.write_f32(*value);
+        }
+    }
+}
+
+pub struct SnapshotReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> SnapshotReader<'a> {
+    // Checks the magic and version, leaving the reader just past them
+    pub fn new(bytes: &'a [u8]) -> Result<Self, SnapshotError> {
+        let mut reader = SnapshotReader { bytes };
+        if reader.take(4, "magic").map_err(|_| SnapshotError::BadMagic)? != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = reader.read_u32("version")?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        Ok(reader)
+    }
+
+    // Fails if anything is left unread
+    pub fn finish(self) -> Result<(), SnapshotError> {
+        match self.bytes.len() {
+            0 => Ok(()),
+            count => Err(SnapshotError::TrailingBytes(count)),
+        }
+    }
+
+    fn take(&mut self, count: usize, field: &'static str) -> Result<&'a [u8], SnapshotError> {
+        if self.bytes.len() < count {
+            return Err(SnapshotError::Truncated(field));
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn take_array<const N: usize>(&mut self, field: &'static str) -> Result<[u8; N], SnapshotError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N, field)?);
+        Ok(array)
+    }
+
+    pub fn read_u8(&mut self, field: &'static str) -> Result<u8, SnapshotError> {
+        Ok(self.take(1, field)?[0])
+    }
+
+    pub fn read_bool(&mut self, field: &'static str) -> Result<bool, SnapshotError> {
+        match self.read_u8(field)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::InvalidValue(field)),
+        }
+    }
+
+    pub fn read_u32(&mut self, field: &'static str) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.take_array(field)?))
+    }
+
+    pub fn read_u64(&mut self, field: &'static str) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take_array(field)?))
+    }
+
+    pub fn read_f32(&mut self, field: &'static str) -> Result<f32, SnapshotError> {
+        Ok(f32::from_le_bytes(self.take_array(field)?))
+    }
+
+    pub fn read_f64(&mut self, field: &'static str) -> Result<f64, SnapshotError> {
+        Ok(f64::from_le_bytes(self.take_array(field)?))
+    }
+
+    // Like read_f64, but infinity and NaN are invalid
+    pub fn read_finite_f64(&mut self, field: &'static str) -> Result<f64, SnapshotError> {
+        let value = self.read_f64(field)?;
+        if !value.is_finite() {
+            return Err(SnapshotError::InvalidValue(field));
+        }
+        Ok(value)
+    }
+
+    // Reads a count of items that each take at least min_item_size bytes,
+    // failing early instead of allocating for a count the data can't hold
+    pub fn read_len(&mut self, min_item_size: usize, field: &'static str) -> Result<usize, SnapshotError> {
+        let len = self.read_u32(field)? as usize;
+        if len.saturating_mul(min_item_size) > self.bytes.len() {
+            return Err(SnapshotError::Truncated(field));
+        }
+        Ok(len)
+    }
+
+    // Reads a u8 discriminant, variants must be listed in discriminant order starting at 0
+    pub fn read_enum<T: Copy>(&mut self, variants: &[T], field: &'static str) -> Result<T, SnapshotError> {
+        let index = self.read_u8(field)? as usize;
+        variants.get(index).copied().ok_or(SnapshotError::InvalidValue(field))
+    }
+
+    pub fn read_f32s(&mut self, len: usize, field: &'static str) -> Result<Vec<f32>, SnapshotError> {
+        let bytes = self.take(len * 4, field)?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect())
+    }
+
+    // Like read_f32s, but infinity and NaN are invalid
+    pub fn read_finite_f32s(&mut self, len: usize, field: &'static str) -> Result<Vec<f32>, SnapshotError> {
+        let values = self.read_f32s(len, field)?;
+        if !values.iter().all(|value| value.is_finite()) {
+            return Err(SnapshotError::InvalidValue(field));
+        }
+        Ok(values)
+    }
+}
+
+pub fn write_settings(writer: &mut SnapshotWriter, settings: &SimulationSettings) {
+    writer.write_f64(settings.gravity_well_mass_multiplier);
+    writer.write_u8(settings.force.law as u8);
+    writer.write_f64(settings.force.distance_scale);
+    writer.write_f64(settings.force.softening);
+    writer.write_f64(settings.force.exponent);
+    writer.write_u8(settings.drag.model as u8);
+    writer.write_f64(settings.drag.linear_coefficient);
+    writer.write_f64(settings.drag.quadratic_coefficient);
+    writer.write_u8(settings.speed_limit.mode as u8);
+    writer.write_f64(settings.speed_limit.max_speed);
+    writer.write_bool(settings.nbody.enabled);
+    writer.write_f64(settings.nbody.theta);
+    writer.write_f64(settings.nbody.strength);
+    writer.write_f64(settings.nbody.particle_mass);
+    writer.write_f64(settings.nbody.softening);
+    writer.write_bool(settings.collisions.collisions_enabled);
+    writer.write_f64(settings.collisions.radius);
+    writer.write_f64(settings.collisions.separation_strength);
+    writer.write_f64(settings.collisions.separation_radius);
+    writer.write_u8(settings.borders.mode as u8);
+    writer.write_f64(settings.borders.restitution);
+    writer.write_f64(settings.particle_lifetime);
+    writer.write_u8(settings.integrator as u8);
+    writer.write_f64(settings.fixed_timestep);
+}
+
+pub fn read_settings(reader: &mut SnapshotReader) -> Result<SimulationSettings, SnapshotError> {
+    let settings = SimulationSettings {
+        gravity_well_mass_multiplier: reader.read_f64("mass multiplier")?,
+        force: ForceConfig {
+            law: reader.read_enum(
+                &[
+                    ForceLaw::InverseLinear,
+                    ForceLaw::InverseSquare,
+                    ForceLaw::Constant,
+                    ForceLaw::Spring,
+                    ForceLaw::Power,
+                ],
+                "force law",
+            )?,
+            distance_scale: reader.read_f64("force distance scale")?,
+            softening: reader.read_f64("force softening")?,
+            exponent: reader.read_f64("force exponent")?,
+        },
+        drag: DragConfig {
+            model: reader.read_enum(
+                &[DragModel::None, DragModel::Linear, DragModel::Quadratic],
+                "drag model",
+            )?,
+            linear_coefficient: reader.read_f64("linear drag coefficient")?,
+            quadratic_coefficient: reader.read_f64("quadratic drag coefficient")?,
+        },
+        speed_limit: SpeedLimit {
+            mode: reader.read_enum(
+                &[SpeedLimitMode::None, SpeedLimitMode::Clamp, SpeedLimitMode::Soft],
+                "speed limit mode",
+            )?,
+            max_speed: reader.read_f64("max speed")?,
+        },
+        nbody: NBodyConfig {
+            enabled: reader.read_bool("n-body enabled")?,
+            theta: reader.read_f64("n-body theta")?,
+            strength: reader.read_f64("n-body strength")?,
+            particle_mass: reader.read_f64("n-body particle mass")?,
+            softening: reader.read_f64("n-body softening")?,
+        },
+        collisions: CollisionConfig {
+            collisions_enabled: reader.read_bool("collisions enabled")?,
+            radius: reader.read_f64("collision radius")?,
+            separation_strength: reader.read_f64("separation strength")?,
+            separation_radius: reader.read_f64("separation radius")?,
+        },
+        borders: Borders {
+            mode: reader.read_enum(
+                &[
+                    BorderMode::Open,
+                    BorderMode::Bounce,
+                    BorderMode::Wrap,
+                    BorderMode::Despawn,
+                ],
+                "border mode",
+            )?,
+            restitution: reader.read_f64("border restitution")?,
+        },
+        particle_lifetime: reader.read_f64("particle lifetime")?,
+        integrator: reader.read_enum(
+            &[
+                Integrator::SemiImplicitEuler,
+                Integrator::VelocityVerlet,
+                Integrator::Rk4,
+            ],
+            "integrator",
+        )?,
+        fixed_timestep: reader.read_f64("fixed timestep")?,
+    };
+    // Same rules as scenes, see SimulationSettings::invalid_field
+    if let Some(field) = settings.invalid_field() {
+        return Err(SnapshotError::InvalidValue(field));
+    }
+    Ok(settings)
+}
+
+pub fn write_rng(writer: &mut SnapshotWriter, rng: &SimRng) {
+    let (state, increment) = rng.state();
+    writer.write_u64(rng.seed());
+    writer.write_u64(state);
+    writer.write_u64(increment);
+}
+
+pub fn read_rng(reader: &mut SnapshotReader) -> Result<SimRng, SnapshotError> {
+    let seed = reader.read_u64("seed")?;
+    let state = reader.read_u64("rng state")?;
+    let increment = reader.read_u64("rng increment")?;
+    SimRng::from_state(seed, state, increment).ok_or(SnapshotError::InvalidValue("rng increment"))
+}
+
+pub fn write_wells(writer: &mut SnapshotWriter, wells: &[GravityWell]) {
+    writer.write_len(wells.len());
+    for well in wells {
+        writer.write_f64(well.pos[0]);
+        writer.write_f64(well.pos[1]);
+        writer.write_f64(well.rotation_deg);
+        writer.write_f64(well.mass);
+        writer.write_u8(well.kind as u8);
+        writer.write_bool(well.is_selected);
+    }
+}
+
+pub fn read_wells(reader: &mut SnapshotReader) -> Result<Vec<GravityWell>, SnapshotError> {
+    let len = reader.read_len(4 * 8 + 2, "gravity wells")?;
+    let mut wells = Vec::with_capacity(len);
+    for _ in 0..len {
+        let pos = [reader.read_finite_f64("well x")?, reader.read_finite_f64("well y")?];
+        let rotation_deg = reader.read_finite_f64("well rotation")?;
+        let mass = reader.read_finite_f64("well mass")?;
+        let kind = reader.read_enum(
+            &[WellKind::Attractor, WellKind::Repulsor, WellKind::Vortex],
+            "well kind",
+        )?;
+        let mut well = GravityWell::new(pos, mass, kind);
+        well.rotation_deg = rotation_deg;
+        well.is_selected = reader.read_bool("well selected")?;
+        wells.push(well);
+    }
+    Ok(wells)
+}
+
+// Per emitter: id u32, shape u8, x f64, y f64, width f64, height f64, rate f64,
+// speed f64, speed spread f64, angle f64, angle spread f64, lifetime f64,
+// spawn accumulator f64, palette length u32, palette colors RRGGBBAA u32
+pub fn write_emitters(writer: &mut SnapshotWriter, emitters: &[Emitter]) {
+    writer.write_len(emitters.len());
+    for emitter in emitters {
+        writer.write_u32(emitter.id);
+        writer.write_u8(emitter.shape as u8);
+        for value in &[
+            emitter.pos[0],
+            emitter.pos[1],
+            emitter.size[0],
+            emitter.size[1],
+            emitter.rate,
+            emitter.speed,
+            emitter.speed_spread,
+            emitter.angle_deg,
+            emitter.angle_spread_deg,
+            emitter.lifetime,
+            emitter.spawn_accumulator,
+        ] {
+            writer.write_f64(*value);
+        }
+        writer.write_len(emitter.palette.len());
+        for color in &emitter.palette {
+            writer.write_u32(color.to_u32());
+        }
+    }
+}
+
+pub fn read_emitters(reader: &mut SnapshotReader) -> Result<Vec<Emitter>, SnapshotError> {
+    let len = reader.read_len(4 + 1 + 11 * 8 + 4, "emitters")?;
+    let mut emitters = Vec::with_capacity(len);
+    for _ in 0..len {
+        let id = reader.read_u32("emitter id")?;
+        let shape = reader.read_enum(
+            &[
+                EmitterShape::Point,
+                EmitterShape::Line,
+                EmitterShape::Circle,
+                EmitterShape::Rectangle,
+            ],
+            "emitter shape",
+        )?;
+        let pos = [reader.read_finite_f64("emitter x")?, reader.read_finite_f64("emitter y")?];
+        let mut emitter = Emitter::new(id, shape, pos);
+        emitter.size = [
+            reader.read_finite_f64("emitter width")?,
+            reader.read_finite_f64("emitter height")?,
+        ];
+        emitter.rate = reader.read_f64("emitter rate")?;
+        if !(0.0..=Emitter::MAX_RATE).contains(&emitter.rate) {
+            return Err(SnapshotError::InvalidValue("emitter rate"));
+        }
+        emitter.speed = reader.read_finite_f64("emitter speed")?;
+        emitter.speed_spread = reader.read_finite_f64("emitter speed spread")?;
+        emitter.angle_deg = reader.read_finite_f64("emitter angle")?;
+        emitter.angle_spread_deg = reader.read_finite_f64("emitter angle spread")?;
+        // Infinite for particles that never expire
+        emitter.lifetime = reader.read_f64("emitter lifetime")?;
+        if emitter.lifetime.is_nan() || emitter.lifetime <= 0.0 {
+            return Err(SnapshotError::InvalidValue("emitter lifetime"));
+        }
+        emitter.spawn_accumulator = reader.read_finite_f64("emitter spawn accumulator")?;
+        if emitter.spawn_accumulator < 0.0 {
+            return Err(SnapshotError::InvalidValue("emitter spawn accumulator"));
+        }
+        let palette_len = reader.read_len(4, "emitter palette")?;
+        for _ in 0..palette_len {
+            emitter.palette.push(Color::from_u32(reader.read_u32("emitter palette")?));
+        }
+        emitters.push(emitter);
+    }
+    Ok(emitters)
+}
+
+pub fn write_particles(writer: &mut SnapshotWriter, particles: &Particles) {
+    writer.write_len(particles.len());
+    writer.write_f32s(particles.pos_x());
+    writer.write_f32s(particles.pos_y());
+    writer.write_f32s(particles.vel_x());
+    writer.write_f32s(particles.vel_y());
+    for color in particles.colors() {
+        writer.write_u32(color.to_u32());
+    }
+    writer.write_f32s(particles.ages());
+    writer.write_f32s(particles.lifetimes());
+}
+
+pub fn read_particles(reader: &mut SnapshotReader) -> Result<Particles, SnapshotError> {
+    let len = reader.read_len(BYTES_PER_PARTICLE, "particles")?;
+    let pos_x = reader.read_finite_f32s(len, "particle x positions")?;
+    let pos_y = reader.read_finite_f32s(len, "particle y positions")?;
+    let vel_x = reader.read_finite_f32s(len, "particle x velocities")?;
+    let vel_y = reader.read_finite_f32s(len, "particle y velocities")?;
+    let colors = (0..len)
+        .map(|_| reader.read_u32("particle colors").map(Color::from_u32))
+        .collect::<Result<_, _>>()?;
+    let ages = reader.read_finite_f32s(len, "particle ages")?;
+    // Infinite for particles that never expire
+    let lifetimes = reader.read_f32s(len, "particle lifetimes")?;
+    if lifetimes.iter().any(|lifetime| lifetime.is_nan()) {
+        return Err(SnapshotError::InvalidValue("particle lifetimes"));
+    }
+    Ok(Particles::from_buffers(pos_x, pos_y, vel_x, vel_y, colors, ages, lifetimes)
+        .expect("every particle buffer is read with the same length"))
+}
+
+// Rough size of a snapshot, used to size the buffer up front
+pub fn estimated_size(particle_count: usize) -> usize {
+    1024 + particle_count * BYTES_PER_PARTICLE
+}
@@ -0,0 +1,264 @@
+// RenderBackend that rasterizes into an RGBA buffer in plain Rust, so frames can be
+// rendered and checked without a browser or GPU. It follows what the WebGl backend
+// does closely enough for tests: source-over alpha blending, lines one pixel wide
+// (anti-aliased here), and rotated quads sampling their texture bilinearly.
+
+use crate::camera::Camera;
+use crate::renderer::{RenderBackend, TexturedQuad};
+use std::collections::HashMap;
+
+pub struct Texture {
+    pub width: u32,
+    pub height: u32,
+    // RGBA, rows from top to bottom
+    pub pixels: Vec<u8>,
+}
+
+impl Texture {
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>) -> Self {
+        assert_eq!(
+            pixels.len(),
+            width as usize * height as usize * 4,
+            "texture pixels don't match its size"
+        );
+        assert!(width > 0 && height > 0, "texture can't be empty");
+        Texture {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    // Bilinear sample at u, v from 0 to 1, clamped to the edge
+    fn sample(&self, u: f32, v: f32) -> [f32; 4] {
+        let x = (u * self.width as f32 - 0.5).clamp(0.0, (self.width - 1) as f32);
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+        let texel = |x: u32, y: u32, channel: usize| {
+            self.pixels[(y * self.width + x) as usize * 4 + channel] as f32 / 255.0
+        };
+        let mut color = [0.0; 4];
+        for (channel, value) in color.iter_mut().enumerate() {
+            let top = texel(x0, y0, channel) * (1.0 - fx) + texel(x1, y0, channel) * fx;
+            let bottom = texel(x0, y1, channel) * (1.0 - fx) + texel(x1, y1, channel) * fx;
+            *value = top * (1.0 - fy) + bottom * fy;
+        }
+        color
+    }
+}
+
+pub struct SoftwareBackend {
+    width: u32,
+    height: u32,
+    // RGBA, rows from top to bottom
+    pixels: Vec<u8>,
+    textures: HashMap<String, Texture>,
+    // Drawn in place of textures that haven't been added, matches the WebGl backend
+    not_found_texture: Texture,
+    // In pixels, lines are one pixel wide in WebGl so this only changes
+    // when rendering at a higher resolution to be scaled down afterwards
+    line_width: f32,
+    camera: Camera,
+}
+
+impl SoftwareBackend {
+    // Starts out fully transparent black
+    pub fn new(width: u32, height: u32) -> Self {
+        SoftwareBackend {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+            textures: HashMap::new(),
+            not_found_texture: Texture::new(1, 1, vec![0, 0, 255, 255]),
+            line_width: 1.0,
+            camera: Camera::fitting(width as f64, height as f64),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = (y * self.width + x) as usize * 4;
+        [
+            self.pixels[i],
+            self.pixels[i + 1],
+            self.pixels[i + 2],
+            self.pixels[i + 3],
+        ]
+    }
+
+    pub fn set_line_width(&mut self, width: f32) {
+        self.line_width = width.max(0.0);
+    }
+
+    pub fn add_texture(&mut self, name: &str, texture: Texture) {
+        self.textures.insert(name.to_owned(), texture);
+    }
+
+    // Where a world position lands in the frame
+    fn world_to_frame(&self, world: [f32; 2]) -> [f32; 2] {
+        let view_size = [self.width as f64, self.height as f64];
+        let [x, y] = self
+            .camera
+            .world_to_screen([world[0] as f64, world[1] as f64], view_size);
+        [x as f32, y as f32]
+    }
+
+    // Blends color over the pixel at x, y with its alpha scaled by coverage,
+    // the same as blendFunc(SRC_ALPHA, ONE_MINUS_SRC_ALPHA) on every channel
+    fn blend(&mut self, x: i64, y: i64, color: [f32; 4], coverage: f32) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+        let alpha = (color[3] * coverage).clamp(0.0, 1.0);
+        if alpha <= 0.0 {
+            return;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        let source = [color[0], color[1], color[2], alpha];
+        for (channel, source) in source.iter().enumerate() {
+            let destination = self.pixels[i + channel] as f32 / 255.0;
+            let blended = source.clamp(0.0, 1.0) * alpha + destination * (1.0 - alpha);
+            self.pixels[i + channel] = to_u8(blended);
+        }
+    }
+
+    // Anti-aliased line, stepping along the longer axis and covering the pixels across it
+    // by how much of each the line's width overlaps. At one pixel wide this splits each
+    // step between the two pixels nearest the line (Xiaolin Wu's algorithm).
+    // Colors are blended from one end to the other
+    fn draw_line(&mut self, from: [f32; 2], to: [f32; 2], from_color: [f32; 4], to_color: [f32; 4]) {
+        let steep = (to[1] - from[1]).abs() > (to[0] - from[0]).abs();
+        // Major and minor axis coordinates of each end
+        let (a0, b0, a1, b1) = if steep {
+            (from[1], from[0], to[1], to[0])
+        } else {
+            (from[0], from[1], to[0], to[1])
+        };
+        if a0 == a1 || !(a0.is_finite() && a1.is_finite() && b0.is_finite() && b1.is_finite()) {
+            return;
+        }
+        let gradient = (b1 - b0) / (a1 - a0);
+        let half_width = self.line_width / 2.0;
+
+        // Every pixel whose center along the major axis lies on the line, clipped to the frame
+        let major_len = if steep { self.height } else { self.width } as f32;
+        let start = (a0.min(a1) - 0.5).ceil().max(0.0);
+        let end = (a0.max(a1) - 0.5).ceil().min(major_len);
+        let mut a = start;
+        while a < end {
+            let center = a + 0.5;
+            let t = (center - a0) / (a1 - a0);
+            let color = lerp_color(from_color, to_color, t);
+            // Span the line covers on the minor axis, pixel edges are at whole numbers
+            let b = b0 + (center - a0) * gradient;
+            let (low, high) = (b - half_width, b + half_width);
+            let major = a as i64;
+            for minor in low.floor() as i64..high.ceil() as i64 {
+                let coverage = high.min(minor as f32 + 1.0) - low.max(minor as f32);
+                if steep {
+                    self.blend(minor, major, color, coverage);
+                } else {
+                    self.blend(major, minor, color, coverage);
+                }
+            }
+            a += 1.0;
+        }
+    }
+
+    fn draw_quad(&mut self, texture: &str, quad: &TexturedQuad) {
+        let center = self.world_to_frame(quad.center);
+        let half_size = quad.half_size * self.camera.zoom as f32;
+        if !half_size.is_finite() || half_size <= 0.0 {
+            return;
+        }
+        let (sin, cos) = quad.rotation_deg.to_radians().sin_cos();
+        // Bounds of the rotated quad, clipped to the frame
+        let reach = half_size * std::f32::consts::SQRT_2;
+        let min_x = (center[0] - reach).floor().max(0.0) as u32;
+        let min_y = (center[1] - reach).floor().max(0.0) as u32;
+        let max_x = ((center[0] + reach).ceil().max(0.0) as u32).min(self.width);
+        let max_y = ((center[1] + reach).ceil().max(0.0) as u32).min(self.height);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                // Pixel center rotated back into the quad's own space
+                let dx = x as f32 + 0.5 - center[0];
+                let dy = y as f32 + 0.5 - center[1];
+                let local_x = dx * cos + dy * sin;
+                let local_y = -dx * sin + dy * cos;
+                if local_x.abs() >= half_size || local_y.abs() >= half_size {
+                    continue;
+                }
+                let u = (local_x + half_size) / (2.0 * half_size);
+                let v = (local_y + half_size) / (2.0 * half_size);
+                let texel = self
+                    .textures
+                    .get(texture)
+                    .unwrap_or(&self.not_found_texture)
+                    .sample(u, v);
+                let mut color = [0.0; 4];
+                for ((value, texel), tint) in color.iter_mut().zip(&texel).zip(&quad.tint) {
+                    *value = (texel * tint).clamp(0.0, 1.0);
+                }
+                self.blend(x as i64, y as i64, color, 1.0);
+            }
+        }
+    }
+}
+
+impl RenderBackend for SoftwareBackend {
+    fn set_camera(&mut self, camera: &Camera) {
+        self.camera = *camera;
+    }
+
+    fn clear(&mut self, color: [f32; 4]) {
+        let color = [to_u8(color[0]), to_u8(color[1]), to_u8(color[2]), to_u8(color[3])];
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    fn draw_lines(&mut self, vertices: &[f32], colors: &[u8]) {
+        for (line, color) in vertices.chunks_exact(4).zip(colors.chunks_exact(8)) {
+            let color: Vec<f32> = color.iter().map(|&c| c as f32 / 255.0).collect();
+            self.draw_line(
+                self.world_to_frame([line[0], line[1]]),
+                self.world_to_frame([line[2], line[3]]),
+                [color[0], color[1], color[2], color[3]],
+                [color[4], color[5], color[6], color[7]],
+            );
+        }
+    }
+
+    fn draw_textured_quads(&mut self, texture: &str, quads: &[TexturedQuad]) {
+        for quad in quads {
+            self.draw_quad(texture, quad);
+        }
+    }
+}
+
+fn lerp_color(from: [f32; 4], to: [f32; 4], t: f32) -> [f32; 4] {
+    let mut color = [0.0; 4];
+    for ((value, from), to) in color.iter_mut().zip(&from).zip(&to) {
+        *value = from + (to - from) * t;
+    }
+    color
+}
+
+fn to_u8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
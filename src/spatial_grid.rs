@@ -0,0 +1,154 @@
+// Uniform grid that buckets particles by position so neighbor queries only
+// have to look at nearby cells instead of scanning every particle.
+// Built with a counting sort, so rebuilding it every tick is cheap.
+
+// Keeps very spread out scenes from allocating absurd numbers of cells,
+// the cells just get bigger instead
+const MAX_CELLS_PER_AXIS: usize = 1024;
+
+pub struct SpatialGrid {
+    // Requested cell size, should be about the largest radius that will be queried
+    cell_size: f64,
+    // Cell size actually used by the last rebuild
+    effective_cell_size: f64,
+    min: [f64; 2],
+    cols: usize,
+    rows: usize,
+    positions: Vec<[f64; 2]>,
+    // Entries for cell i are entries[cell_starts[i]..cell_starts[i + 1]]
+    cell_starts: Vec<usize>,
+    entries: Vec<usize>,
+}
+
+impl SpatialGrid {
+    pub fn new(cell_size: f64) -> Self {
+        SpatialGrid {
+            cell_size,
+            effective_cell_size: cell_size,
+            min: [0.0, 0.0],
+            cols: 0,
+            rows: 0,
+            positions: Vec::new(),
+            cell_starts: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn set_cell_size(&mut self, cell_size: f64) {
+        self.cell_size = cell_size;
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    // Replaces the contents of the grid, entries are identified by their
+    // index in the positions iterator
+    pub fn rebuild<I>(&mut self, positions: I)
+    where
+        I: Iterator<Item = [f64; 2]>,
+    {
+        self.positions.clear();
+        self.positions.extend(positions);
+
+        let mut min = [0.0, 0.0];
+        let mut max = [0.0, 0.0];
+        if let Some(first) = self.positions.first() {
+            min = *first;
+            max = *first;
+        }
+        for pos in &self.positions {
+            min = [min[0].min(pos[0]), min[1].min(pos[1])];
+            max = [max[0].max(pos[0]), max[1].max(pos[1])];
+        }
+
+        let extent = f64::max(max[0] - min[0], max[1] - min[1]);
+        self.effective_cell_size = f64::max(self.cell_size, extent / MAX_CELLS_PER_AXIS as f64);
+        self.min = min;
+        self.cols = ((max[0] - min[0]) / self.effective_cell_size) as usize + 1;
+        self.rows = ((max[1] - min[1]) / self.effective_cell_size) as usize + 1;
+
+        // Count how many entries land in each cell, turn the counts into
+        // start offsets, then drop every entry into its slot
+        let num_cells = self.cols * self.rows;
+        self.cell_starts.clear();
+        self.cell_starts.resize(num_cells + 1, 0);
+        for i in 0..self.positions.len() {
+            let (col, row) = self.cell_of(self.positions[i]);
+            self.cell_starts[row * self.cols + col + 1] += 1;
+        }
+        for i in 0..num_cells {
+            self.cell_starts[i + 1] += self.cell_starts[i];
+        }
+        let mut next_slot = self.cell_starts.clone();
+        self.entries.clear();
+        self.entries.resize(self.positions.len(), 0);
+        for i in 0..self.positions.len() {
+            let (col, row) = self.cell_of(self.positions[i]);
+            let cell = row * self.cols + col;
+            self.entries[next_slot[cell]] = i;
+            next_slot[cell] += 1;
+        }
+    }
+
+    // Column and row of the cell containing pos, clamped to the grid
+    fn cell_of(&self, pos: [f64; 2]) -> (usize, usize) {
+        let col = ((pos[0] - self.min[0]) / self.effective_cell_size).max(0.0) as usize;
+        let row = ((pos[1] - self.min[1]) / self.effective_cell_size).max(0.0) as usize;
+        (col.min(self.cols - 1), row.min(self.rows - 1))
+    }
+
+    fn cell_entries(&self, col: usize, row: usize) -> &[usize] {
+        let cell = row * self.cols + col;
+        &self.entries[self.cell_starts[cell]..self.cell_starts[cell + 1]]
+    }
+
+    // Calls f with the index and position of every entry within radius of center
+    pub fn for_each_within<F>(&self, center: [f64; 2], radius: f64, mut f: F)
+    where
+        F: FnMut(usize, [f64; 2]),
+    {
+        if self.positions.is_empty() {
+            return;
+        }
+        let (min_col, min_row) = self.cell_of([center[0] - radius, center[1] - radius]);
+        let (max_col, max_row) = self.cell_of([center[0] + radius, center[1] + radius]);
+        let radius_squared = radius * radius;
+        for row in min_row..=max_row {
+            for col in min_col..=max_col {
+                for &i in self.cell_entries(col, row) {
+                    let pos = self.positions[i];
+                    if vecmath::vec2_square_len(vecmath::vec2_sub(pos, center)) <= radius_squared {
+                        f(i, pos);
+                    }
+                }
+            }
+        }
+    }
+
+    // Indices of every entry within radius of center
+    pub fn query_radius(&self, center: [f64; 2], radius: f64) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.for_each_within(center, radius, |i, _| found.push(i));
+        found
+    }
+
+    // Calls f once for every pair of entries (i, j) with i < j that are
+    // within radius of each other
+    pub fn for_each_pair_within<F>(&self, radius: f64, mut f: F)
+    where
+        F: FnMut(usize, usize),
+    {
+        for i in 0..self.positions.len() {
+            self.for_each_within(self.positions[i], radius, |j, _| {
+                if i < j {
+                    f(i, j);
+                }
+            });
+        }
+    }
+}
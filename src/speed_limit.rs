@@ -0,0 +1,46 @@
+// Optional limit on how fast particles can move, keeps particles that pass
+// close to a well from shooting off with absurdly long trails
+
+use crate::particle::Particle;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpeedLimitMode {
+    None = 0,
+    // Speeds above the limit are cut down to exactly the limit
+    Clamp = 1,
+    // Speeds are smoothly squashed with tanh so they approach but never reach the limit
+    Soft = 2,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpeedLimit {
+    pub mode: SpeedLimitMode,
+    pub max_speed: f64,
+}
+
+impl Default for SpeedLimit {
+    fn default() -> Self {
+        SpeedLimit {
+            mode: SpeedLimitMode::None,
+            max_speed: Particle::MAX_VELOCITY,
+        }
+    }
+}
+
+impl SpeedLimit {
+    pub fn apply(&self, vel: [f64; 2]) -> [f64; 2] {
+        let speed = vecmath::vec2_len(vel);
+        if speed == 0.0 {
+            return vel;
+        }
+        let new_speed = match self.mode {
+            SpeedLimitMode::None => return vel,
+            SpeedLimitMode::Clamp => f64::min(speed, self.max_speed),
+            SpeedLimitMode::Soft => self.max_speed * (speed / self.max_speed).tanh(),
+        };
+        vecmath::vec2_scale(vel, new_speed / speed)
+    }
+}
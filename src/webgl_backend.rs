@@ -0,0 +1,412 @@
+// RenderBackend that draws with WebGl calls, holds the WebGl context along with
+// textures, the matrix for projecting into normalized screen coordinates,
+// buffers and shaders.
+
+use wasm_bindgen::JsCast;
+use crate::camera::Camera;
+use crate::renderer::{RenderBackend, TexturedQuad};
+use crate::webgl_helpers;
+use web_sys::{ console, HtmlCanvasElement, WebGlRenderingContext, WebGlBuffer, WebGlProgram, WebGlTexture };
+use std::collections::HashMap;
+extern crate nalgebra_glm as glm;
+use glm::TMat4;
+
+pub struct WebGlBackend {
+    pub canvas: HtmlCanvasElement,
+    pub context: WebGlRenderingContext,
+    pub textures: HashMap<String, Option<WebGlTexture>>,
+    pub projection_mat: TMat4<f32>,
+    // Canvas size in CSS pixels
+    pub view_size: [f64; 2],
+    pub camera: Camera,
+    pub particle_vertex_buffer: WebGlBuffer,
+    pub particle_color_buffer: WebGlBuffer,
+    pub gravity_well_vbo: WebGlBuffer,
+    pub particle_shader: WebGlProgram,
+    pub gravity_well_shader: WebGlProgram,
+}
+
+impl WebGlBackend {
+    // On creation grabs reference to WebGl context from canvas on the DOM
+    // Tries to compile shaders and link them into shader programs
+    pub fn new(canvas: &HtmlCanvasElement) -> Self {
+        let context = canvas
+            .get_context("webgl")
+            .unwrap()
+            .unwrap()
+            .dyn_into::<WebGlRenderingContext>()
+            .unwrap();
+
+        // Projections matrix that converts world x, y coordinates into
+        // normalized screen coordinates for webgl
+        let view_size = [canvas.width() as f64, canvas.height() as f64];
+        let camera = Camera::fitting(view_size[0], view_size[1]);
+        let projection_mat = camera.projection(view_size);
+
+        // Compile shader programs
+        let particle_shader = compile_particle_shader(&context)
+            .expect("Failed to compile particle shader.");
+        let gravity_well_shader = compile_gravity_well_shader(&context)
+            .expect("Failed to compile gravity well shader.");
+
+        // Enable alpha blending for the webGl context
+        context.enable(WebGlRenderingContext::BLEND);
+        context.blend_func(
+            WebGlRenderingContext::SRC_ALPHA,
+            WebGlRenderingContext::ONE_MINUS_SRC_ALPHA,
+        );
+        // TODO Set position and color location explicitly (before or after linking?)
+        let particle_vertex_buffer = context
+            .create_buffer()
+            .ok_or("failed to create buffer")
+            .unwrap();
+        let particle_color_buffer = context
+            .create_buffer()
+            .ok_or("failed to create buffer")
+            .unwrap();
+
+        let gravity_well_vbo = context
+            .create_buffer()
+            .ok_or("failed to create buffer")
+            .unwrap();
+
+        // Hashmap for storing named textures, creates a texture of one blue pixel
+        // to use as a default when a requested texture isn't found in the hashmap
+        let mut textures = HashMap::new();
+        let not_found_texture = context.create_texture();
+        context.bind_texture(
+            WebGlRenderingContext::TEXTURE_2D,
+            not_found_texture.as_ref(),
+        );
+        context
+            .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+                WebGlRenderingContext::TEXTURE_2D,
+                0,
+                WebGlRenderingContext::RGBA as i32,
+                1,
+                1,
+                0,
+                WebGlRenderingContext::RGBA,
+                WebGlRenderingContext::UNSIGNED_BYTE,
+                Some(&[0u8, 0u8, 255u8, 255u8]),
+            )
+            .expect("failed to create not_found texture");
+        textures.insert("not_found".to_owned(), not_found_texture);
+
+        WebGlBackend {
+            canvas: canvas.clone(),
+            context,
+            textures,
+            projection_mat,
+            view_size,
+            camera,
+            particle_vertex_buffer,
+            particle_color_buffer,
+            gravity_well_vbo,
+            particle_shader,
+            gravity_well_shader,
+        }
+    }
+
+    // Sizes the canvas to width by height CSS pixels times pixel_ratio, while
+    // drawing coordinates stay in CSS pixels
+    pub fn resize(&mut self, width: u32, height: u32, pixel_ratio: f64) {
+        let pixel_ratio = if pixel_ratio.is_finite() && pixel_ratio > 0.0 {
+            pixel_ratio
+        } else {
+            1.0
+        };
+        let device_width = ((width as f64 * pixel_ratio).round() as u32).max(1);
+        let device_height = ((height as f64 * pixel_ratio).round() as u32).max(1);
+        self.canvas.set_width(device_width);
+        self.canvas.set_height(device_height);
+        self.context
+            .viewport(0, 0, device_width as i32, device_height as i32);
+        self.view_size = [width as f64, height as f64];
+        self.projection_mat = self.camera.projection(self.view_size);
+    }
+}
+
+impl RenderBackend for WebGlBackend {
+    fn set_camera(&mut self, camera: &Camera) {
+        self.camera = *camera;
+        self.projection_mat = camera.projection(self.view_size);
+    }
+
+    fn clear(&mut self, color: [f32; 4]) {
+        self.context.clear_color(color[0], color[1], color[2], color[3]);
+        self.context.clear(WebGlRenderingContext::COLOR_BUFFER_BIT);
+    }
+
+    fn draw_lines(&mut self, vertices: &[f32], colors: &[u8]) {
+        self.context.use_program(Some(&self.particle_shader));
+
+        let position_attrib_location = self
+            .context
+            .get_attrib_location(&self.particle_shader, "a_Position");
+        let color_attrib_location = self
+            .context
+            .get_attrib_location(&self.particle_shader, "a_Color");
+        if position_attrib_location < 0 || color_attrib_location < 0 {
+            console::log_1(&"Invalid attribute location".into());
+        }
+
+        self.context.bind_buffer(
+            WebGlRenderingContext::ARRAY_BUFFER,
+            Some(&self.particle_vertex_buffer),
+        );
+        unsafe {
+            let vertex_array = js_sys::Float32Array::view(vertices);
+            self.context.buffer_data_with_array_buffer_view(
+                WebGlRenderingContext::ARRAY_BUFFER,
+                &vertex_array,
+                WebGlRenderingContext::DYNAMIC_DRAW,
+            );
+        }
+        self.context.vertex_attrib_pointer_with_i32(
+            position_attrib_location as u32,
+            2,
+            WebGlRenderingContext::FLOAT,
+            false,
+            0,
+            0,
+        );
+        self.context
+            .enable_vertex_attrib_array(position_attrib_location as u32);
+
+        self.context.bind_buffer(
+            WebGlRenderingContext::ARRAY_BUFFER,
+            Some(&self.particle_color_buffer),
+        );
+        unsafe {
+            let color_array = js_sys::Uint8Array::view(colors);
+            self.context.buffer_data_with_array_buffer_view(
+                WebGlRenderingContext::ARRAY_BUFFER,
+                &color_array,
+                WebGlRenderingContext::DYNAMIC_DRAW,
+            );
+        }
+
+        self.context.vertex_attrib_pointer_with_i32(
+            color_attrib_location as u32,
+            4,
+            WebGlRenderingContext::UNSIGNED_BYTE,
+            true,
+            0,
+            0,
+        );
+        self.context
+            .enable_vertex_attrib_array(color_attrib_location as u32);
+
+        let u_proj_location = self
+            .context
+            .get_uniform_location(&self.particle_shader, "u_Proj")
+            .expect("Failed to get u_Proj uniform location");
+        self.context.uniform_matrix4fv_with_f32_array(
+            Some(&u_proj_location),
+            false,
+            self.projection_mat.as_slice(),
+        );
+
+        self.context
+            .draw_arrays(WebGlRenderingContext::LINES, 0, (vertices.len() / 2) as i32);
+    }
+
+    fn draw_textured_quads(&mut self, texture: &str, quads: &[TexturedQuad]) {
+        // Coordinates are x, y, u, t for a quad one unit from its center to each edge,
+        // the model matrix scales it up to each quad's size
+        let vertex_array = vec![
+            // Triangle 1:
+            // top right
+            1.0, -1.0, 1.0, 0.0,
+            // top left
+            -1.0, -1.0, 0.0, 0.0,
+            // bottom left
+            -1.0, 1.0, 0.0, 1.0,
+
+            // Triangle 2:
+            // top right
+            1.0, -1.0, 1.0, 0.0,
+            // bottom left
+            -1.0, 1.0, 0.0, 1.0,
+            // bottom right
+            1.0, 1.0, 1.0, 1.0f32,
+        ];
+        self.context.use_program(Some(&self.gravity_well_shader));
+        self.context.bind_buffer(
+            WebGlRenderingContext::ARRAY_BUFFER,
+            Some(&self.gravity_well_vbo),
+        );
+        unsafe {
+            let vertex_array = js_sys::Float32Array::view(&vertex_array);
+            self.context.buffer_data_with_array_buffer_view(
+                WebGlRenderingContext::ARRAY_BUFFER,
+                &vertex_array,
+                WebGlRenderingContext::DYNAMIC_DRAW,
+            );
+        }
+
+        let u_proj_location = self
+            .context
+            .get_uniform_location(&self.gravity_well_shader, "u_Proj")
+            .expect("failed to get u_Proj uniform location");
+        self.context.uniform_matrix4fv_with_f32_array(
+            Some(&u_proj_location),
+            false,
+            self.projection_mat.as_slice(),
+        );
+
+        let u_model_location = self
+            .context
+            .get_uniform_location(&self.gravity_well_shader, "u_Model")
+            .expect("failed to get u_Model uniform location");
+
+        let u_sampler_location = self
+            .context
+            .get_uniform_location(&self.gravity_well_shader, "u_Sampler")
+            .expect("failed to get u_Sampler uniform location");
+
+        let u_tint_location = self
+            .context
+            .get_uniform_location(&self.gravity_well_shader, "u_Tint")
+            .expect("failed to get u_Tint uniform location");
+
+        let position_attrib_location = self
+            .context
+            .get_attrib_location(&self.gravity_well_shader, "a_Position");
+
+        let tex_coord_attrib_location = self
+            .context
+            .get_attrib_location(&self.gravity_well_shader, "a_TexCoord");
+
+        let stride = (std::mem::size_of::<f32>() * 2) + (std::mem::size_of::<f32>() * 2);
+        self.context.vertex_attrib_pointer_with_i32(
+            position_attrib_location as u32,
+            2,
+            WebGlRenderingContext::FLOAT,
+            false,
+            stride as i32,
+            0,
+        );
+        self.context.vertex_attrib_pointer_with_i32(
+            tex_coord_attrib_location as u32,
+            2,
+            WebGlRenderingContext::FLOAT,
+            false,
+            stride as i32,
+            (std::mem::size_of::<f32>() * 2) as i32,
+        );
+        self.context
+            .enable_vertex_attrib_array(position_attrib_location as u32);
+        self.context
+            .enable_vertex_attrib_array(tex_coord_attrib_location as u32);
+        let quad_tex = self
+            .textures
+            .get(texture)
+            .or(self.textures.get("not_found"))
+            .expect("failed to load 'not_found' texture");
+
+        self.context.active_texture(WebGlRenderingContext::TEXTURE0);
+        self.context
+            .bind_texture(WebGlRenderingContext::TEXTURE_2D, quad_tex.as_ref());
+        self.context.uniform1i(Some(&u_sampler_location), 0);
+
+        for quad in quads {
+            let model_mat = glm::scale(
+                &glm::rotate_z(
+                    &glm::translation(&glm::vec3(quad.center[0], quad.center[1], 0.0)),
+                    quad.rotation_deg.to_radians(),
+                ),
+                &glm::vec3(quad.half_size, quad.half_size, 1.0),
+            );
+            self.context.uniform_matrix4fv_with_f32_array(
+                Some(&u_model_location),
+                false,
+                model_mat.as_slice(),
+            );
+            self.context
+                .uniform4fv_with_f32_array(Some(&u_tint_location), &quad.tint);
+            self.context
+                .draw_arrays(WebGlRenderingContext::TRIANGLES, 0, 6);
+        }
+    }
+}
+
+fn compile_particle_shader(context: &WebGlRenderingContext) -> Result<WebGlProgram, String> {
+    let vertex_shader = webgl_helpers::compile_shader(
+        context,
+        WebGlRenderingContext::VERTEX_SHADER,
+        r#"
+        attribute vec2 a_Position;
+        attribute vec4 a_Color;
+
+        // uniform float u_TrailScale;
+        uniform mat4 u_Proj;
+
+        varying vec4 v_Color;
+
+        void main() {
+            gl_Position = u_Proj * vec4(a_Position, 0.0, 1.0);
+            v_Color = a_Color;
+        }
+    "#,
+    )?;
+    let fragment_shader = webgl_helpers::compile_shader(
+        context,
+        WebGlRenderingContext::FRAGMENT_SHADER,
+        r#"
+
+        precision mediump float;
+        varying vec4 v_Color;
+
+        void main() {
+            gl_FragColor = v_Color;
+        }
+    "#,
+    )?;
+
+    webgl_helpers::link_program(context, &vertex_shader, &fragment_shader)
+}
+
+fn compile_gravity_well_shader(context: &WebGlRenderingContext) -> Result<WebGlProgram, String> {
+    let vertex_shader = webgl_helpers::compile_shader(
+        context,
+        WebGlRenderingContext::VERTEX_SHADER,
+        r#"
+        attribute vec2 a_Position;
+        attribute vec2 a_TexCoord;
+
+        uniform mat4 u_Model;
+        uniform mat4 u_Proj;
+
+        varying mediump vec2 v_TexCoord;
+
+        void main() {
+            gl_Position = u_Proj * u_Model * vec4(a_Position, 0.0, 1.0);
+            v_TexCoord = a_TexCoord;
+        }
+        "#,
+    )?;
+    let fragment_shader = webgl_helpers::compile_shader(
+        context,
+        WebGlRenderingContext::FRAGMENT_SHADER,
+        r#"
+        precision mediump float;
+
+        varying vec2 v_TexCoord;
+
+        uniform vec4 u_Tint;
+        uniform sampler2D u_Sampler;
+
+        void main() {
+            gl_FragColor = texture2D(u_Sampler, v_TexCoord) * u_Tint;
+        }
+    "#,
+    )?;
+
+    webgl_helpers::link_program(
+        context,
+        &vertex_shader,
+        &fragment_shader,
+    )
+}
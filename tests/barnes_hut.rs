@@ -0,0 +1,56 @@
+// Checks the Barnes-Hut approximation against summing every particle directly
+
+mod common;
+
+use rust_webgl_particles_backend::barnes_hut::{NBodyConfig, QuadTree};
+use common::random_positions;
+
+fn direct_acceleration(positions: &[[f64; 2]], pos: [f64; 2], config: &NBodyConfig) -> [f64; 2] {
+    let mut total = [0.0, 0.0];
+    for &other in positions {
+        let diff = vecmath::vec2_sub(other, pos);
+        let softened = vecmath::vec2_square_len(diff) + config.softening * config.softening;
+        let scale = config.strength * config.particle_mass / (softened * softened.sqrt());
+        total = vecmath::vec2_add(total, vecmath::vec2_scale(diff, scale));
+    }
+    total
+}
+
+#[test]
+fn zero_theta_matches_direct_sum() {
+    let positions = random_positions(3, 500, [0.0, 0.0], [800.0, 600.0]);
+    let config = NBodyConfig {
+        theta: 0.0,
+        ..NBodyConfig::default()
+    };
+    let tree = QuadTree::build(positions.iter().copied(), config.particle_mass);
+
+    for &pos in positions.iter().take(20) {
+        let approx = tree.acceleration(pos, &config);
+        let exact = direct_acceleration(&positions, pos, &config);
+        assert!((approx[0] - exact[0]).abs() < 1e-6);
+        assert!((approx[1] - exact[1]).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn default_theta_is_close_to_direct_sum() {
+    let positions = random_positions(3, 2000, [0.0, 0.0], [800.0, 600.0]);
+    let config = NBodyConfig::default();
+    let tree = QuadTree::build(positions.iter().copied(), config.particle_mass);
+
+    for &pos in positions.iter().take(20) {
+        let approx = tree.acceleration(pos, &config);
+        let exact = direct_acceleration(&positions, pos, &config);
+        let error = vecmath::vec2_len(vecmath::vec2_sub(approx, exact));
+        assert!(error <= 0.1 * vecmath::vec2_len(exact));
+    }
+}
+
+#[test]
+fn coincident_particles_do_not_recurse_forever() {
+    let positions = vec![[10.0, 10.0]; 100];
+    let tree = QuadTree::build(positions.iter().copied(), 1.0);
+    let acc = tree.acceleration([50.0, 10.0], &NBodyConfig::default());
+    assert!(acc[0] < 0.0);
+}
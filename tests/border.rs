@@ -0,0 +1,95 @@
+// What each border mode does to particles at the edges of the simulation area
+
+use rust_webgl_particles_backend::border::{BorderMode, Borders};
+use rust_webgl_particles_backend::drag::{DragConfig, DragModel};
+use rust_webgl_particles_backend::simulation::Simulation;
+
+fn borders(mode: BorderMode) -> Borders {
+    Borders {
+        mode,
+        restitution: 0.5,
+    }
+}
+
+#[test]
+fn open_borders_leave_particles_alone() {
+    let mut pos = [-50.0, 700.0];
+    let mut vel = [-10.0, 10.0];
+    assert!(borders(BorderMode::Open).apply(&mut pos, &mut vel, 800.0, 600.0));
+    assert_eq!((pos, vel), ([-50.0, 700.0], [-10.0, 10.0]));
+}
+
+#[test]
+fn bounce_reflects_inwards_with_restitution() {
+    let bounce = borders(BorderMode::Bounce);
+
+    // Past the left and bottom edges, heading out
+    let mut pos = [-5.0, 610.0];
+    let mut vel = [-100.0, 40.0];
+    assert!(bounce.apply(&mut pos, &mut vel, 800.0, 600.0));
+    assert_eq!(pos, [0.0, 599.0]);
+    assert_eq!(vel, [50.0, -20.0]);
+
+    // Past the right edge but already heading back in, still points inwards
+    let mut pos = [900.0, 300.0];
+    let mut vel = [-100.0, 0.0];
+    assert!(bounce.apply(&mut pos, &mut vel, 800.0, 600.0));
+    assert_eq!(pos, [799.0, 300.0]);
+    assert_eq!(vel, [-50.0, 0.0]);
+
+    // Same on the top edge
+    let mut pos = [300.0, -20.0];
+    let mut vel = [0.0, 80.0];
+    bounce.apply(&mut pos, &mut vel, 800.0, 600.0);
+    assert_eq!(vel, [0.0, 40.0]);
+
+    // Inside the area nothing changes
+    let mut pos = [300.0, 300.0];
+    let mut vel = [-100.0, 100.0];
+    bounce.apply(&mut pos, &mut vel, 800.0, 600.0);
+    assert_eq!((pos, vel), ([300.0, 300.0], [-100.0, 100.0]));
+}
+
+#[test]
+fn wrap_brings_particles_back_on_the_opposite_edge() {
+    let wrap = borders(BorderMode::Wrap);
+    let mut vel = [10.0, -10.0];
+
+    let mut pos = [805.0, -5.0];
+    assert!(wrap.apply(&mut pos, &mut vel, 800.0, 600.0));
+    assert_eq!(pos, [5.0, 595.0]);
+
+    let mut pos = [-5.0, 600.0];
+    assert!(wrap.apply(&mut pos, &mut vel, 800.0, 600.0));
+    assert_eq!(pos, [795.0, 0.0]);
+    assert_eq!(vel, [10.0, -10.0]);
+}
+
+#[test]
+fn despawn_removes_particles_outside_the_area() {
+    let despawn = borders(BorderMode::Despawn);
+    let mut vel = [0.0, 0.0];
+    assert!(despawn.apply(&mut [0.0, 0.0], &mut vel, 800.0, 600.0));
+    assert!(despawn.apply(&mut [799.5, 599.5], &mut vel, 800.0, 600.0));
+    assert!(!despawn.apply(&mut [-0.5, 300.0], &mut vel, 800.0, 600.0));
+    assert!(!despawn.apply(&mut [300.0, 600.0], &mut vel, 800.0, 600.0));
+}
+
+#[test]
+fn bounced_particles_come_back_in_after_the_area_shrinks() {
+    let mut sim = Simulation::with_seed(800, 600, 1);
+    sim.set_drag_config(DragConfig {
+        model: DragModel::None,
+        ..DragConfig::default()
+    });
+    sim.set_borders(borders(BorderMode::Bounce));
+    sim.spawn_particle(700.0, 300.0, -120.0, 0.0);
+    sim.resize(400, 300, false);
+
+    for _ in 0..30 {
+        sim.tick(1.0 / 60.0);
+    }
+    let particle = sim.particles().get(0);
+    assert_eq!(particle.vel, [-60.0, 0.0]);
+    assert!(particle.pos[0] < 399.0 - 20.0, "{:?}", particle.pos);
+}
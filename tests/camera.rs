@@ -0,0 +1,144 @@
+// Camera conversions between the screen and the world, and drawing through it
+
+extern crate nalgebra_glm as glm;
+
+use rust_webgl_particles_backend::camera::Camera;
+use rust_webgl_particles_backend::frame_export::{FrameExportError, FrameExportOptions, FrameRenderer};
+use rust_webgl_particles_backend::renderer::{RenderBackend, TexturedQuad};
+use rust_webgl_particles_backend::simulation::Simulation;
+use rust_webgl_particles_backend::software_backend::SoftwareBackend;
+
+const VIEW: [f64; 2] = [800.0, 600.0];
+
+fn assert_close(a: [f64; 2], b: [f64; 2]) {
+    assert!((a[0] - b[0]).abs() < 1e-9 && (a[1] - b[1]).abs() < 1e-9, "{:?} != {:?}", a, b);
+}
+
+#[test]
+fn fitting_camera_maps_screen_pixels_to_the_same_world_point() {
+    let camera = Camera::fitting(VIEW[0], VIEW[1]);
+    for &point in &[[0.0, 0.0], [800.0, 600.0], [123.0, 456.0]] {
+        assert_close(camera.screen_to_world(point, VIEW), point);
+    }
+}
+
+#[test]
+fn screen_and_world_conversions_round_trip() {
+    let camera = Camera::new([-250.0, 1000.0], 3.5);
+    let screen = [37.0, 590.0];
+    let world = camera.screen_to_world(screen, VIEW);
+    assert_close(camera.world_to_screen(world, VIEW), screen);
+    // The middle of the view is always the center
+    assert_close(camera.screen_to_world([400.0, 300.0], VIEW), [-250.0, 1000.0]);
+}
+
+#[test]
+fn zooming_keeps_the_point_under_the_mouse_in_place() {
+    let mut camera = Camera::fitting(VIEW[0], VIEW[1]);
+    let mouse = [600.0, 150.0];
+    let before = camera.screen_to_world(mouse, VIEW);
+
+    camera.zoom_at(mouse, 4.0, VIEW);
+    assert_eq!(camera.zoom, 4.0);
+    assert_close(camera.screen_to_world(mouse, VIEW), before);
+
+    camera.zoom_at(mouse, 0.1, VIEW);
+    assert!((camera.zoom - 0.4).abs() < 1e-12);
+    assert_close(camera.screen_to_world(mouse, VIEW), before);
+}
+
+#[test]
+fn zoom_is_clamped_and_bad_factors_are_ignored() {
+    let mut camera = Camera::fitting(VIEW[0], VIEW[1]);
+    camera.zoom_at([0.0, 0.0], 1e6, VIEW);
+    assert_eq!(camera.zoom, Camera::MAX_ZOOM);
+    camera.zoom_at([0.0, 0.0], 1e-9, VIEW);
+    assert_eq!(camera.zoom, Camera::MIN_ZOOM);
+
+    let before = camera;
+    for &factor in &[0.0, -2.0, f64::NAN, f64::INFINITY] {
+        camera.zoom_at([10.0, 10.0], factor, VIEW);
+        assert_eq!(camera, before);
+    }
+    assert_eq!(Camera::new([0.0, 0.0], 1000.0).zoom, Camera::MAX_ZOOM);
+}
+
+#[test]
+fn panning_moves_the_world_with_the_mouse() {
+    let mut camera = Camera::new([400.0, 300.0], 2.0);
+    let grabbed = camera.screen_to_world([100.0, 100.0], VIEW);
+    camera.pan_by(50.0, -20.0);
+    assert_close(camera.screen_to_world([150.0, 80.0], VIEW), grabbed);
+}
+
+#[test]
+fn projection_puts_the_view_edges_at_the_clip_space_edges() {
+    let camera = Camera::new([100.0, 50.0], 2.0);
+    let projection = camera.projection(VIEW);
+    let clip = |world: [f64; 2]| {
+        let point = projection * glm::vec4(world[0] as f32, world[1] as f32, 0.0, 1.0);
+        [point.x as f64, point.y as f64]
+    };
+    let close = |a: [f64; 2], b: [f64; 2]| (a[0] - b[0]).abs() < 1e-5 && (a[1] - b[1]).abs() < 1e-5;
+
+    // Top left of the screen is the top left of clip space, y points down
+    assert!(close(clip(camera.screen_to_world([0.0, 0.0], VIEW)), [-1.0, 1.0]));
+    assert!(close(clip(camera.screen_to_world(VIEW, VIEW)), [1.0, -1.0]));
+    assert!(close(clip([100.0, 50.0]), [0.0, 0.0]));
+}
+
+#[test]
+fn software_backend_draws_through_the_camera() {
+    let quad = TexturedQuad {
+        center: [20.0, 20.0],
+        half_size: 4.0,
+        rotation_deg: 0.0,
+        tint: [1.0, 1.0, 1.0, 1.0],
+    };
+    let covered = |camera: Camera| {
+        let mut backend = SoftwareBackend::new(40, 40);
+        backend.set_camera(&camera);
+        backend.draw_textured_quads("missing", &[quad]);
+        backend.pixels().chunks(4).filter(|p| p[3] > 0).count()
+    };
+
+    let normal = covered(Camera::fitting(40.0, 40.0));
+    // Zooming in on the quad makes it cover four times the pixels
+    let zoomed = covered(Camera::new([20.0, 20.0], 2.0));
+    assert_eq!(zoomed, normal * 4);
+    // Looking somewhere else leaves it out of view
+    assert_eq!(covered(Camera::new([500.0, 500.0], 1.0)), 0);
+}
+
+#[test]
+fn frame_export_can_follow_a_camera() {
+    let mut sim = Simulation::with_seed(200, 100, 5);
+    sim.spawn_gravity_well(150.0, 50.0);
+    let options = FrameExportOptions {
+        width: 64,
+        height: 64,
+        frame_count: 1,
+        supersampling: 1,
+        ..FrameExportOptions::default()
+    };
+    let pixel = |pixels: &[u8], x: usize, y: usize| pixels[(y * 64 + x) * 4..(y * 64 + x) * 4 + 4].to_vec();
+
+    // Zoomed into the well it fills the middle of the frame
+    let mut renderer = FrameRenderer::new(FrameExportOptions {
+        camera: Some(Camera::new([150.0, 50.0], 1.0)),
+        ..options
+    })
+    .unwrap();
+    let pixels = renderer.render(&sim);
+    assert_eq!(pixel(&pixels, 32, 32), vec![0, 0, 255, 255]);
+    assert_eq!(pixel(&pixels, 32, 16), vec![0, 0, 255, 255]);
+    assert_eq!(pixel(&pixels, 2, 2), vec![0, 0, 0, 255]);
+
+    match FrameRenderer::new(FrameExportOptions {
+        camera: Some(Camera { center: [0.0, 0.0], zoom: 0.0 }),
+        ..options
+    }) {
+        Err(FrameExportError::InvalidOptions(_)) => {}
+        _ => panic!("expected a zero zoom camera to be rejected"),
+    }
+}
@@ -0,0 +1,70 @@
+// Fixtures shared between the integration tests and benches.
+// Not every test file uses every helper
+#![allow(dead_code)]
+
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use rust_webgl_particles_backend::border::BorderMode;
+use rust_webgl_particles_backend::emitter::EmitterShape;
+use rust_webgl_particles_backend::gravity_well::WellKind;
+use rust_webgl_particles_backend::particle::Particles;
+use rust_webgl_particles_backend::simulation::Simulation;
+
+// count positions spread uniformly over the rectangle from min to max
+pub fn random_positions(seed: u64, count: usize, min: [f64; 2], max: [f64; 2]) -> Vec<[f64; 2]> {
+    let mut rng = Pcg32::seed_from_u64(seed);
+    (0..count)
+        .map(|_| {
+            [
+                min[0] + rng.gen::<f64>() * (max[0] - min[0]),
+                min[1] + rng.gen::<f64>() * (max[1] - min[1]),
+            ]
+        })
+        .collect()
+}
+
+// 800x600 simulation with one well of each kind and particle_count randomly placed particles
+pub fn simulation_with_wells(seed: u64, particle_count: u32) -> Simulation {
+    let mut sim = Simulation::with_seed(800, 600, seed);
+    sim.spawn_gravity_well(400.0, 300.0);
+    sim.spawn_gravity_well_of_kind(200.0, 150.0, WellKind::Repulsor);
+    sim.spawn_gravity_well_of_kind(600.0, 450.0, WellKind::Vortex);
+    sim.initialize_particles(particle_count);
+    sim
+}
+
+// Simulation using most of what can be saved: wells of different kinds and masses,
+// a bouncing border, particles that expire and ones that don't, and an emitter.
+// It has been stepped a little, so it has leftover time in its accumulator
+pub fn busy_simulation() -> Simulation {
+    let mut sim = Simulation::with_seed(800, 600, 77);
+    sim.spawn_gravity_well(400.0, 300.0);
+    sim.spawn_gravity_well_of_kind(100.0, 120.0, WellKind::Vortex);
+    sim.spawn_gravity_well_of_kind(650.0, 100.0, WellKind::Repulsor);
+    sim.set_well_mass(1, 40.0);
+    sim.set_gravity_well_mass_multiplier(2.5);
+    sim.set_border_mode(BorderMode::Bounce);
+    sim.set_particle_lifetime(6.0);
+    sim.initialize_particles(200);
+    sim.set_particle_lifetime(0.0);
+    sim.initialize_particles(5);
+    let id = sim.add_emitter(EmitterShape::Circle, 200.0, 200.0);
+    let emitter = sim.emitter_mut(id).unwrap();
+    emitter.rate = 45.0;
+    emitter.lifetime = f64::INFINITY;
+    sim.step(0.41);
+    sim
+}
+
+// Asserts every attribute of every particle matches exactly
+pub fn assert_same_particles(a: &Simulation, b: &Simulation) {
+    let (a, b) = (a.particles(), b.particles());
+    assert_eq!(a.pos_x(), b.pos_x());
+    assert_eq!(a.pos_y(), b.pos_y());
+    assert_eq!(a.vel_x(), b.vel_x());
+    assert_eq!(a.vel_y(), b.vel_y());
+    assert_eq!(a.ages(), b.ages());
+    assert_eq!(a.lifetimes(), b.lifetimes());
+    let colors = |p: &Particles| p.colors().iter().map(|c| c.to_u32()).collect::<Vec<_>>();
+    assert_eq!(colors(a), colors(b));
+}
@@ -0,0 +1,159 @@
+// Exporting rendered frames as PNG sequences
+
+mod common;
+
+use rust_webgl_particles_backend::frame_export::{
+    export_frames, load_png_texture, FrameExportError, FrameExportOptions, FrameRenderer,
+};
+use rust_webgl_particles_backend::gravity_well::GravityWell;
+use rust_webgl_particles_backend::simulation::Simulation;
+use common::busy_simulation;
+use std::path::{Path, PathBuf};
+
+fn small_options() -> FrameExportOptions {
+    FrameExportOptions {
+        width: 64,
+        height: 32,
+        frame_count: 4,
+        supersampling: 2,
+        ..FrameExportOptions::default()
+    }
+}
+
+// Fresh directory under the target dir for a test's output
+fn output_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("target/test-output/frame_export")
+        .join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn writes_numbered_frames_at_the_requested_resolution() {
+    let dir = output_dir("numbered");
+    let mut sim = busy_simulation();
+    let ticks_before = sim.tick_count();
+    let paths = export_frames(&mut sim, small_options(), Vec::new(), &dir).unwrap();
+
+    let names: Vec<_> = paths
+        .iter()
+        .map(|path| path.file_name().unwrap().to_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(
+        names,
+        vec!["frame_00000.png", "frame_00001.png", "frame_00002.png", "frame_00003.png"]
+    );
+
+    // Reading a frame back through the texture loader gives the output size
+    let frame = load_png_texture(&paths[3]).unwrap();
+    assert_eq!((frame.width, frame.height), (64, 32));
+    // One tick for each of the three frames after the first
+    assert_eq!(sim.tick_count() - ticks_before, 3);
+}
+
+#[test]
+fn frames_keep_real_time_when_the_timestep_does_not_divide_them() {
+    // 24 fps against 60 ticks a second is two and a half ticks per frame
+    for &(fps, ticks_per_frame) in &[(24.0, 3), (25.0, 3), (30.0, 2), (144.0, 1)] {
+        let mut sim = busy_simulation();
+        let ticks_before = sim.tick_count();
+        let rotation_before = sim.gravity_wells()[0].rotation_deg;
+        let options = FrameExportOptions {
+            frame_dt: 1.0 / fps,
+            ..small_options()
+        };
+        export_frames(&mut sim, options, Vec::new(), &output_dir("real_time")).unwrap();
+        assert_eq!(sim.tick_count() - ticks_before, 3 * ticks_per_frame);
+        // Wells turn at a fixed speed, so their rotation shows how much time passed
+        let turned = sim.gravity_wells()[0].rotation_deg - rotation_before;
+        let expected = GravityWell::ROTATION_SPEED * 3.0 / fps;
+        assert!((turned - expected).abs() < 1e-6, "{} fps turned {}", fps, turned);
+    }
+}
+
+#[test]
+fn frames_that_take_no_time_are_rejected() {
+    let mut sim = busy_simulation();
+    let options = FrameExportOptions {
+        frame_dt: 0.0,
+        ..small_options()
+    };
+    match export_frames(&mut sim, options, Vec::new(), &output_dir("no_time")) {
+        Err(FrameExportError::InvalidOptions(_)) => {}
+        _ => panic!("expected a frame_dt of zero to be rejected"),
+    }
+}
+
+#[test]
+fn frames_match_the_simulation_state() {
+    // Exporting is deterministic, the same simulation renders the same frames
+    let mut a = busy_simulation();
+    let mut b = busy_simulation();
+    let dir_a = output_dir("deterministic_a");
+    let dir_b = output_dir("deterministic_b");
+    let frames_a = export_frames(&mut a, small_options(), Vec::new(), &dir_a).unwrap();
+    let frames_b = export_frames(&mut b, small_options(), Vec::new(), &dir_b).unwrap();
+    for (a, b) in frames_a.iter().zip(&frames_b) {
+        assert_eq!(std::fs::read(a).unwrap(), std::fs::read(b).unwrap());
+    }
+    // And frames change as it moves
+    assert_ne!(std::fs::read(&frames_a[0]).unwrap(), std::fs::read(&frames_a[3]).unwrap());
+}
+
+#[test]
+fn simulation_is_fit_into_the_frame() {
+    // 200x100 simulation into a 64x64 frame leaves bars above and below
+    let mut renderer = FrameRenderer::new(FrameExportOptions {
+        width: 64,
+        height: 64,
+        ..small_options()
+    })
+    .unwrap();
+    let mut sim = Simulation::with_seed(200, 100, 3);
+    sim.spawn_gravity_well(100.0, 50.0);
+    let pixels = renderer.render(&sim);
+    assert_eq!(pixels.len(), 64 * 64 * 4);
+
+    let pixel = |x: usize, y: usize| &pixels[(y * 64 + x) * 4..(y * 64 + x) * 4 + 4];
+    // The well is drawn with the default blue texture in the middle
+    assert_eq!(pixel(32, 32), &[0, 0, 255, 255]);
+    // Scaled down with the rest of the scene, a radius of 20 becomes about 6 pixels
+    assert_eq!(pixel(32, 24), &[0, 0, 0, 255]);
+}
+
+#[test]
+fn supersampling_keeps_trails_about_as_bright() {
+    let mut sim = Simulation::with_seed(64, 32, 4);
+    sim.spawn_particle(10.0, 16.0, -400.0, 0.0);
+
+    let brightness = |supersampling: u32| {
+        let mut renderer = FrameRenderer::new(FrameExportOptions {
+            supersampling,
+            ..small_options()
+        })
+        .unwrap();
+        let pixels = renderer.render(&sim);
+        pixels.chunks(4).map(|p| p[0] as u32 + p[1] as u32 + p[2] as u32).sum::<u32>()
+    };
+    let (plain, supersampled) = (brightness(1), brightness(4));
+    assert!(plain > 0);
+    let ratio = supersampled as f64 / plain as f64;
+    assert!((0.8..1.25).contains(&ratio), "ratio {}", ratio);
+}
+
+#[test]
+fn bad_options_are_rejected() {
+    for options in &[
+        FrameExportOptions { width: 0, ..small_options() },
+        FrameExportOptions { supersampling: 0, ..small_options() },
+        FrameExportOptions { supersampling: 9, ..small_options() },
+        FrameExportOptions { frame_dt: -1.0, ..small_options() },
+        FrameExportOptions { frame_dt: f64::NAN, ..small_options() },
+    ] {
+        match FrameRenderer::new(*options) {
+            Err(FrameExportError::InvalidOptions(_)) => {}
+            _ => panic!("expected {:?} to be rejected", options),
+        }
+    }
+}
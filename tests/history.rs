@@ -0,0 +1,89 @@
+// Undo/redo of gravity well edits
+
+use rust_webgl_particles_backend::gravity_well::WellKind;
+use rust_webgl_particles_backend::simulation::Simulation;
+
+fn well_positions(sim: &Simulation) -> Vec<[f64; 2]> {
+    sim.gravity_wells().iter().map(|well| well.pos).collect()
+}
+
+#[test]
+fn spawning_and_removing_wells_can_be_undone() {
+    let mut sim = Simulation::with_seed(800, 600, 1);
+    sim.spawn_gravity_well(100.0, 100.0);
+    sim.spawn_gravity_well_of_kind(300.0, 300.0, WellKind::Vortex);
+    sim.spawn_gravity_well(500.0, 500.0);
+
+    // Remove the middle well, undoing puts it back in the same place in the list
+    sim.try_removing(300.0, 300.0);
+    assert_eq!(well_positions(&sim), vec![[100.0, 100.0], [500.0, 500.0]]);
+    assert!(sim.undo());
+    assert_eq!(well_positions(&sim), vec![[100.0, 100.0], [300.0, 300.0], [500.0, 500.0]]);
+    assert_eq!(sim.gravity_wells()[1].kind, WellKind::Vortex);
+
+    assert!(sim.redo());
+    assert_eq!(sim.gravity_wells().len(), 2);
+    assert!(!sim.redo());
+
+    // Undo everything, including the spawns
+    while sim.undo() {}
+    assert!(sim.gravity_wells().is_empty());
+    assert!(!sim.can_undo());
+    while sim.redo() {}
+    assert_eq!(well_positions(&sim), vec![[100.0, 100.0], [500.0, 500.0]]);
+}
+
+#[test]
+fn a_drag_is_undone_in_one_step() {
+    let mut sim = Simulation::with_seed(800, 600, 1);
+    sim.spawn_gravity_well(100.0, 100.0);
+
+    assert!(sim.try_selecting(100.0, 100.0));
+    for _ in 0..10 {
+        sim.move_selection_by(5.0, -2.0);
+    }
+    sim.release_selection();
+    assert_eq!(well_positions(&sim), vec![[150.0, 80.0]]);
+
+    // A second drag gets its own entry
+    assert!(sim.try_selecting(150.0, 80.0));
+    sim.move_selection_by(10.0, 0.0);
+    sim.move_selection_by(10.0, 0.0);
+    sim.release_selection();
+
+    assert!(sim.undo());
+    assert_eq!(well_positions(&sim), vec![[150.0, 80.0]]);
+    assert!(sim.undo());
+    assert_eq!(well_positions(&sim), vec![[100.0, 100.0]]);
+    assert!(sim.redo());
+    assert_eq!(well_positions(&sim), vec![[150.0, 80.0]]);
+}
+
+#[test]
+fn mass_changes_can_be_undone() {
+    let mut sim = Simulation::with_seed(800, 600, 1);
+    sim.spawn_gravity_well(100.0, 100.0);
+    let original = sim.get_well_mass(0).unwrap();
+    sim.set_well_mass(0, 10.0);
+    sim.set_well_mass(0, 20.0);
+
+    assert!(sim.undo());
+    assert_eq!(sim.get_well_mass(0), Some(10.0));
+    assert!(sim.undo());
+    assert_eq!(sim.get_well_mass(0), Some(original));
+    assert!(sim.redo());
+    assert_eq!(sim.get_well_mass(0), Some(10.0));
+}
+
+#[test]
+fn new_edits_clear_the_redo_history() {
+    let mut sim = Simulation::with_seed(800, 600, 1);
+    sim.spawn_gravity_well(100.0, 100.0);
+    sim.spawn_gravity_well(200.0, 200.0);
+    assert!(sim.undo());
+    assert!(sim.can_redo());
+
+    sim.spawn_gravity_well(300.0, 300.0);
+    assert!(!sim.can_redo());
+    assert_eq!(well_positions(&sim), vec![[100.0, 100.0], [300.0, 300.0]]);
+}
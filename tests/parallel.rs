@@ -0,0 +1,51 @@
+// Checks that splitting the update across threads gives the same result as running it serially
+#![cfg(feature = "parallel")]
+
+mod common;
+
+use rust_webgl_particles_backend::barnes_hut::NBodyConfig;
+use rust_webgl_particles_backend::integrator::Integrator;
+use rust_webgl_particles_backend::simulation::Simulation;
+use common::simulation_with_wells;
+
+// Enough particles for several chunks, plus a partial one at the end
+fn scene(parallel_enabled: bool, integrator: Integrator, nbody_enabled: bool) -> Simulation {
+    let mut sim = simulation_with_wells(5, 4321);
+    sim.set_parallel_enabled(parallel_enabled);
+    sim.set_integrator(integrator);
+    sim.set_nbody_config(NBodyConfig {
+        enabled: nbody_enabled,
+        ..NBodyConfig::default()
+    });
+    sim
+}
+
+fn assert_parallel_matches_serial(integrator: Integrator, nbody_enabled: bool) {
+    let mut parallel = scene(true, integrator, nbody_enabled);
+    let mut serial = scene(false, integrator, nbody_enabled);
+    for _ in 0..10 {
+        parallel.tick(1.0 / 60.0);
+        serial.tick(1.0 / 60.0);
+    }
+
+    assert_eq!(parallel.particles().pos_x(), serial.particles().pos_x());
+    assert_eq!(parallel.particles().pos_y(), serial.particles().pos_y());
+    assert_eq!(parallel.particles().vel_x(), serial.particles().vel_x());
+    assert_eq!(parallel.particles().vel_y(), serial.particles().vel_y());
+}
+
+#[test]
+fn parallel_matches_serial_for_every_integrator() {
+    for &integrator in &[
+        Integrator::SemiImplicitEuler,
+        Integrator::VelocityVerlet,
+        Integrator::Rk4,
+    ] {
+        assert_parallel_matches_serial(integrator, false);
+    }
+}
+
+#[test]
+fn parallel_matches_serial_with_nbody() {
+    assert_parallel_matches_serial(Integrator::SemiImplicitEuler, true);
+}
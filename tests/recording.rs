@@ -0,0 +1,169 @@
+// Recording input and replaying it deterministically
+
+mod common;
+
+use rust_webgl_particles_backend::emitter::EmitterShape;
+use rust_webgl_particles_backend::gravity_well::WellKind;
+use rust_webgl_particles_backend::recording::{
+    InputEvent, Recorder, Recording, RecordingError, Replay, RECORDING_VERSION,
+};
+use rust_webgl_particles_backend::simulation::Simulation;
+use common::assert_same_particles;
+
+// Applies an input to sim and records it, the way WasmApp does
+fn input(recorder: &mut Recorder, sim: &mut Simulation, event: InputEvent) {
+    event.apply(sim);
+    recorder.record(sim, event);
+}
+
+fn record_session(sim: &mut Simulation) -> Recording {
+    let mut recorder = Recorder::start(sim);
+    input(&mut recorder, sim, InputEvent::InitializeParticles { count: 300 });
+    input(
+        &mut recorder,
+        sim,
+        InputEvent::SpawnGravityWell {
+            x: 400.0,
+            y: 300.0,
+            kind: WellKind::Attractor,
+        },
+    );
+    sim.step(0.25);
+
+    // Drag the well somewhere else
+    input(&mut recorder, sim, InputEvent::TrySelecting { x: 400.0, y: 300.0 });
+    for _ in 0..5 {
+        input(&mut recorder, sim, InputEvent::MoveSelectionBy { x: 20.0, y: -10.0 });
+        sim.step(0.05);
+    }
+    input(&mut recorder, sim, InputEvent::ReleaseSelection);
+
+    // Settings changed directly are picked up without an explicit event
+    sim.set_particle_lifetime(2.0);
+    recorder.sync_settings(sim);
+    input(
+        &mut recorder,
+        sim,
+        InputEvent::AddEmitter {
+            shape: EmitterShape::Circle,
+            x: 100.0,
+            y: 100.0,
+        },
+    );
+    let mut emitter = sim.emitters()[0].clone();
+    emitter.rate = 60.0;
+    input(&mut recorder, sim, InputEvent::SetEmitter { emitter });
+    sim.step(0.5);
+
+    input(
+        &mut recorder,
+        sim,
+        InputEvent::SpawnParticle {
+            x: 10.0,
+            y: 20.0,
+            vel_x: 30.0,
+            vel_y: 0.0,
+        },
+    );
+    input(&mut recorder, sim, InputEvent::Undo);
+    input(&mut recorder, sim, InputEvent::RemoveParticles { count: 50 });
+    sim.step(0.3);
+    recorder.finish(sim)
+}
+
+#[test]
+fn replay_matches_the_recorded_session() {
+    let mut original = Simulation::with_seed(800, 600, 77);
+    let recording = record_session(&mut original);
+    let json = recording.to_json();
+
+    // Replaying starts from the recorded scene, whatever state sim was in
+    let mut replayed = Simulation::with_seed(800, 600, 1);
+    replayed.initialize_particles(10);
+    replayed.set_particle_lifetime(9.0);
+    Recording::from_json(&json).unwrap().replay(&mut replayed);
+
+    assert_eq!(replayed.get_particle_lifetime(), 2.0);
+    assert_eq!(replayed.gravity_wells()[0].pos, original.gravity_wells()[0].pos);
+    assert_eq!(replayed.emitters()[0].rate, 60.0);
+    assert_same_particles(&original, &replayed);
+}
+
+#[test]
+fn replay_can_be_stepped_like_a_live_session() {
+    let mut original = Simulation::with_seed(800, 600, 5);
+    let recording = record_session(&mut original);
+
+    let mut replayed = Simulation::with_seed(800, 600, 5);
+    let mut replay = Replay::start(recording, &mut replayed);
+    // Frame deltas don't have to line up with the recorded ones
+    while !replay.is_finished(&replayed) {
+        replayed.step_with(0.037, |sim| replay.apply_due_events(sim));
+    }
+    replay.apply_due_events(&mut replayed);
+    // The last frame may have run a few ticks past the end of the recording
+    let extra_ticks = replayed.tick_count() - original.tick_count();
+    for _ in 0..extra_ticks {
+        original.tick(original.get_fixed_timestep());
+    }
+    assert_same_particles(&original, &replayed);
+}
+
+#[test]
+fn replay_uses_the_recorded_size() {
+    let mut original = Simulation::with_seed(800, 600, 12);
+    let recording = record_session(&mut original);
+
+    // Particles bounce off the recorded borders, not the ones of the smaller simulation
+    let mut replayed = Simulation::with_seed(320, 200, 12);
+    recording.replay(&mut replayed);
+    assert_eq!((replayed.width(), replayed.height()), (800, 600));
+    assert_same_particles(&original, &replayed);
+
+    // Stopping a replay part way goes back to the size it was started at
+    let mut stopped = Simulation::with_seed(320, 200, 12);
+    let mut replay = Replay::start(recording, &mut stopped);
+    assert_eq!((stopped.width(), stopped.height()), (800, 600));
+    stopped.step_with(0.5, |sim| replay.apply_due_events(sim));
+    replay.set_live_size(640, 400);
+    replay.stop(&mut stopped);
+    assert_eq!((stopped.width(), stopped.height()), (640, 400));
+}
+
+#[test]
+fn replay_undoes_settings_changed_while_it_plays() {
+    let mut original = Simulation::with_seed(800, 600, 8);
+    let recording = record_session(&mut original);
+
+    let mut replayed = Simulation::with_seed(800, 600, 8);
+    let mut replay = Replay::start(recording, &mut replayed);
+    replayed.step_with(0.2, |sim| replay.apply_due_events(sim));
+    replayed.set_gravity_well_mass_multiplier(50.0);
+    while !replay.is_finished(&replayed) {
+        replayed.step_with(1.0 / 60.0, |sim| replay.apply_due_events(sim));
+    }
+    replay.apply_due_events(&mut replayed);
+    assert_eq!(replayed.tick_count(), original.tick_count());
+    assert_same_particles(&original, &replayed);
+}
+
+#[test]
+fn bad_recordings_are_rejected() {
+    let mut sim = Simulation::with_seed(800, 600, 3);
+    let json = record_session(&mut sim).to_json();
+
+    let newer = json.replacen(
+        &format!("\"version\":{}", RECORDING_VERSION),
+        &format!("\"version\":{}", RECORDING_VERSION + 1),
+        1,
+    );
+    match Recording::from_json(&newer) {
+        Err(RecordingError::UnsupportedVersion(version)) => assert_eq!(version, RECORDING_VERSION + 1),
+        _ => panic!("expected an unsupported version error"),
+    }
+
+    match Recording::from_json(&json[..json.len() / 2]) {
+        Err(RecordingError::Malformed(_)) => {}
+        _ => panic!("expected a malformed recording error"),
+    }
+}
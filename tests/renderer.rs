@@ -0,0 +1,108 @@
+// Renderer staging arrays staying in sync with the particles however they change
+
+use rust_webgl_particles_backend::border::BorderMode;
+use rust_webgl_particles_backend::camera::Camera;
+use rust_webgl_particles_backend::emitter::EmitterShape;
+use rust_webgl_particles_backend::renderer::{RenderBackend, Renderer, TexturedQuad};
+use rust_webgl_particles_backend::simulation::Simulation;
+
+// Backend that keeps what it was asked to draw instead of drawing it
+#[derive(Default)]
+struct RecordingBackend {
+    lines: Vec<f32>,
+    colors: Vec<u8>,
+}
+
+impl RenderBackend for RecordingBackend {
+    fn set_camera(&mut self, _camera: &Camera) {}
+
+    fn clear(&mut self, _color: [f32; 4]) {}
+
+    fn draw_lines(&mut self, vertices: &[f32], colors: &[u8]) {
+        self.lines = vertices.to_vec();
+        self.colors = colors.to_vec();
+    }
+
+    fn draw_textured_quads(&mut self, _texture: &str, _quads: &[TexturedQuad]) {}
+}
+
+type Operation = (&'static str, fn(&mut Simulation));
+
+const OPERATIONS: [Operation; 6] = [
+    ("spawn", |sim| sim.spawn_particle(20.0, 20.0, 500.0, 0.0)),
+    ("initialize", |sim| sim.initialize_particles(40)),
+    ("remove", |sim| sim.remove_particles(25)),
+    ("clear", |sim| sim.clear_particles()),
+    // Particles expire and leave through the despawning borders
+    ("step", |sim| sim.step(0.1)),
+    ("emit", |sim| {
+        let id = sim.add_emitter(EmitterShape::Point, 50.0, 50.0);
+        sim.emitter_mut(id).unwrap().rate = 200.0;
+    }),
+];
+
+fn assert_in_sync(renderer: &Renderer<RecordingBackend>, sim: &Simulation, steps: &[&str]) {
+    let particles = sim.particles();
+    assert_eq!(renderer.particle_vertices().len(), particles.len() * 4, "after {:?}", steps);
+    assert_eq!(renderer.particle_colors().len(), particles.len() * 8, "after {:?}", steps);
+    assert_eq!(renderer.backend.lines.len(), particles.len() * 4, "after {:?}", steps);
+    assert_eq!(renderer.backend.colors.len(), particles.len() * 8, "after {:?}", steps);
+    for (i, line) in renderer.backend.lines.chunks(4).enumerate() {
+        assert_eq!([line[0], line[1]], [particles.pos_x()[i], particles.pos_y()[i]], "after {:?}", steps);
+    }
+}
+
+fn render(renderer: &mut Renderer<RecordingBackend>, sim: &Simulation) {
+    renderer.clear_screen();
+    renderer.render_particles(sim.particles(), 0.1);
+    renderer.render_gravity_wells(sim.gravity_wells());
+}
+
+#[test]
+fn every_order_of_changes_keeps_the_renderer_in_sync() {
+    let n = OPERATIONS.len();
+    for sequence in 0..n.pow(4) {
+        let operations: Vec<Operation> = (0..4)
+            .map(|place| OPERATIONS[sequence / n.pow(place) % n])
+            .collect();
+        let names: Vec<&str> = operations.iter().map(|(name, _)| *name).collect();
+
+        // Rendering after every change, and only once everything has happened,
+        // like particles being made before the canvas is connected
+        for &render_every_step in &[true, false] {
+            let mut sim = Simulation::with_seed(100, 100, sequence as u64);
+            sim.set_border_mode(BorderMode::Despawn);
+            sim.set_particle_lifetime(0.15);
+            sim.spawn_gravity_well(50.0, 50.0);
+            let mut renderer = Renderer::new(RecordingBackend::default());
+
+            for (i, (_, operation)) in operations.iter().enumerate() {
+                operation(&mut sim);
+                if render_every_step {
+                    render(&mut renderer, &sim);
+                    assert_in_sync(&renderer, &sim, &names[..=i]);
+                }
+            }
+            render(&mut renderer, &sim);
+            assert_in_sync(&renderer, &sim, &names);
+        }
+    }
+}
+
+#[test]
+fn arrays_shrink_with_the_particles() {
+    let mut sim = Simulation::with_seed(100, 100, 1);
+    let mut renderer = Renderer::new(RecordingBackend::default());
+    sim.initialize_particles(500);
+    render(&mut renderer, &sim);
+    assert_eq!(renderer.particle_vertices().len(), 2000);
+
+    sim.remove_particles(450);
+    render(&mut renderer, &sim);
+    assert_eq!(renderer.particle_vertices().len(), 200);
+
+    sim.clear_particles();
+    render(&mut renderer, &sim);
+    assert!(renderer.particle_vertices().is_empty());
+    assert!(renderer.backend.lines.is_empty());
+}
@@ -0,0 +1,39 @@
+// The random number generator simulations draw from
+
+use rand::{Rng, RngCore, SeedableRng};
+use rand_pcg::Pcg32;
+use rust_webgl_particles_backend::rng::SimRng;
+
+#[test]
+fn draws_the_same_sequence_as_pcg32() {
+    // Seeded simulations lay out the same particles they always have
+    let mut rng = SimRng::new(42);
+    let mut pcg = Pcg32::seed_from_u64(42);
+    for _ in 0..100 {
+        assert_eq!(rng.next_u32(), pcg.next_u32());
+        assert_eq!(rng.next_u64(), pcg.next_u64());
+        assert_eq!(rng.gen::<f64>(), pcg.gen::<f64>());
+        assert_eq!(rng.gen_range(0, 7), pcg.gen_range(0, 7));
+    }
+}
+
+#[test]
+fn continues_from_its_saved_state() {
+    let mut original = SimRng::new(1234);
+    for _ in 0..57 {
+        original.gen::<f64>();
+        original.gen::<u8>();
+    }
+    let (state, increment) = original.state();
+    let mut restored = SimRng::from_state(original.seed(), state, increment).unwrap();
+    assert_eq!(restored.seed(), 1234);
+    for _ in 0..20 {
+        assert_eq!(restored.gen::<u32>(), original.gen::<u32>());
+    }
+}
+
+#[test]
+fn even_increments_are_rejected() {
+    let (state, increment) = SimRng::new(5).state();
+    assert!(SimRng::from_state(5, state, increment & !1).is_none());
+}
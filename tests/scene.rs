@@ -0,0 +1,108 @@
+// Saving scenes to JSON and loading them back
+
+mod common;
+
+use rust_webgl_particles_backend::scene::{Scene, SceneError, SCENE_VERSION};
+use rust_webgl_particles_backend::simulation::Simulation;
+use common::busy_simulation;
+
+fn assert_same_state(a: &Simulation, b: &Simulation) {
+    assert_eq!(a.settings(), b.settings());
+    assert_eq!(a.seed(), b.seed());
+    assert_eq!(a.particles().pos_x(), b.particles().pos_x());
+    assert_eq!(a.particles().pos_y(), b.particles().pos_y());
+    assert_eq!(a.particles().vel_x(), b.particles().vel_x());
+    assert_eq!(a.particles().vel_y(), b.particles().vel_y());
+    assert_eq!(a.particles().ages(), b.particles().ages());
+    assert_eq!(a.particles().lifetimes(), b.particles().lifetimes());
+    assert_eq!(a.gravity_wells().len(), b.gravity_wells().len());
+    for (wa, wb) in a.gravity_wells().iter().zip(b.gravity_wells()) {
+        assert_eq!((wa.pos, wa.mass, wa.kind), (wb.pos, wb.mass, wb.kind));
+    }
+    assert_eq!(a.emitters().len(), b.emitters().len());
+}
+
+#[test]
+fn scene_survives_a_round_trip() {
+    let original = busy_simulation();
+    let json = original.to_scene().to_json();
+
+    let mut loaded = Simulation::with_seed(320, 240, 1);
+    loaded.load_scene(Scene::from_json(&json).unwrap());
+
+    assert_same_state(&original, &loaded);
+    assert!(loaded.particles().iter().any(|p| p.lifetime.is_infinite()));
+    assert!(loaded.emitters()[0].lifetime.is_infinite());
+    // Loading keeps the size of whatever the simulation is shown on
+    assert_eq!((loaded.width(), loaded.height()), (320, 240));
+}
+
+#[test]
+fn loaded_scene_continues_like_the_original() {
+    let mut original = busy_simulation();
+    let mut loaded = Simulation::with_seed(800, 600, 1);
+    loaded.load_scene(Scene::from_json(&original.to_scene().to_json()).unwrap());
+    // The original had leftover time in its accumulator, drop it so both tick in step
+    original.load_scene(Scene::from_json(&original.to_scene().to_json()).unwrap());
+
+    for _ in 0..30 {
+        original.tick(1.0 / 60.0);
+        loaded.tick(1.0 / 60.0);
+    }
+    assert_same_state(&original, &loaded);
+}
+
+#[test]
+fn malformed_json_is_reported() {
+    match Scene::from_json("{ \"version\": 1, \"width\": ") {
+        Err(SceneError::Malformed(err)) => assert!(err.line() > 0),
+        _ => panic!("expected a malformed scene error"),
+    }
+    match Scene::from_json("{ \"version\": 1 }") {
+        Err(SceneError::Malformed(err)) => assert!(err.to_string().contains("missing field")),
+        _ => panic!("expected a malformed scene error"),
+    }
+}
+
+#[test]
+fn unknown_versions_are_rejected() {
+    let json = busy_simulation().to_scene().to_json().replacen(
+        &format!("\"version\": {}", SCENE_VERSION),
+        "\"version\": 999",
+        1,
+    );
+    match Scene::from_json(&json) {
+        Err(SceneError::UnsupportedVersion(999)) => {}
+        _ => panic!("expected an unsupported version error"),
+    }
+}
+
+#[test]
+fn unusable_settings_are_rejected() {
+    let mut scene = busy_simulation().to_scene();
+    scene.settings.fixed_timestep = 0.0;
+    match Scene::from_json(&scene.to_json()) {
+        Err(SceneError::Invalid(reason)) => assert!(reason.contains("fixed_timestep")),
+        _ => panic!("expected an invalid scene error"),
+    }
+}
+
+#[test]
+fn numbers_the_simulation_cant_run_with_are_rejected() {
+    // JSON has no infinity or NaN, but finite numbers can be just as unusable
+    let json = busy_simulation().to_scene().to_json();
+    let replacements = [
+        ("\"rate\": 45.0", "\"rate\": 1e12"),
+        ("\"rate\": 45.0", "\"rate\": -45.0"),
+        ("\"restitution\": 1.0", "\"restitution\": 3.0"),
+        ("\"max_speed\": 2000.0", "\"max_speed\": 0.0"),
+        ("\"theta\": 0.5", "\"theta\": -0.5"),
+    ];
+    for (from, to) in &replacements {
+        assert!(json.contains(from), "scene JSON has no {}", from);
+        match Scene::from_json(&json.replacen(from, to, 1)) {
+            Err(SceneError::Invalid(_)) => {}
+            _ => panic!("expected {} to be rejected", to),
+        }
+    }
+}
@@ -0,0 +1,525 @@
+// Native tests for the renderer-independent Simulation
+
+use rand::SeedableRng;
+use rust_webgl_particles_backend::border::BorderMode;
+use rust_webgl_particles_backend::color::Color;
+use rust_webgl_particles_backend::drag::{DragConfig, DragModel};
+use rust_webgl_particles_backend::emitter::{Emitter, EmitterShape};
+use rust_webgl_particles_backend::force_law::{ForceConfig, ForceLaw};
+use rust_webgl_particles_backend::gravity_well::WellKind;
+use rust_webgl_particles_backend::renderer::particle_trail_lines;
+use rust_webgl_particles_backend::simulation::{Simulation, MAX_STEP_SECONDS};
+use rust_webgl_particles_backend::speed_limit::{SpeedLimit, SpeedLimitMode};
+
+fn simulation_with_well(seed: u64) -> Simulation {
+    let mut sim = Simulation::with_seed(800, 600, seed);
+    sim.spawn_gravity_well(400.0, 300.0);
+    sim.initialize_particles(50);
+    sim
+}
+
+// Velocity a resting particle at (x, y) picks up in one tick from a well at (400, 300),
+// with drag turned off so only the well is pulling
+fn velocity_after_one_tick<F>(x: f64, y: f64, configure: F) -> [f64; 2]
+where
+    F: FnOnce(&mut Simulation),
+{
+    let mut sim = Simulation::with_seed(800, 600, 1);
+    sim.set_drag_config(DragConfig {
+        model: DragModel::None,
+        ..DragConfig::default()
+    });
+    sim.spawn_gravity_well(400.0, 300.0);
+    sim.spawn_particle(x, y, 0.0, 0.0);
+    configure(&mut sim);
+    sim.tick(sim.get_fixed_timestep());
+    sim.particles().vel(0)
+}
+
+fn assert_near(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() <= 1e-4 * expected.abs().max(1.0),
+        "{} != {}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn spawn_step_select_and_remove() {
+    let mut sim = Simulation::with_seed(800, 600, 9);
+    sim.spawn_particle(100.0, 100.0, 60.0, 0.0);
+    sim.spawn_gravity_well(400.0, 300.0);
+    assert_eq!(sim.particles().len(), 1);
+    assert_eq!(sim.gravity_wells().len(), 1);
+
+    // Pulled towards the well while moving right
+    sim.step(0.5);
+    let particle = sim.particles().get(0);
+    assert!(particle.pos[0] > 100.0);
+    assert!(particle.pos[1] > 100.0);
+
+    assert!(!sim.try_selecting(100.0, 100.0));
+    assert_eq!(sim.selected_well_index(), None);
+    assert!(sim.try_selecting(410.0, 295.0));
+    assert_eq!(sim.selected_well_index(), Some(0));
+    sim.move_selection_by(10.0, 0.0);
+    sim.release_selection();
+    assert_eq!(sim.gravity_wells()[0].pos, [410.0, 300.0]);
+
+    // Missing the well leaves it alone
+    sim.try_removing(100.0, 100.0);
+    assert_eq!(sim.gravity_wells().len(), 1);
+    sim.try_removing(405.0, 305.0);
+    assert!(sim.gravity_wells().is_empty());
+}
+
+#[test]
+fn selecting_and_removing_hit_the_same_area() {
+    // Just outside the well's radius, rounding or truncating would bring these inside
+    let mut sim = Simulation::with_seed(800, 600, 1);
+    sim.spawn_gravity_well(100.0, 100.0);
+    assert!(!sim.try_selecting(79.6, 100.0));
+    sim.try_removing(120.4, 100.0);
+    assert_eq!(sim.gravity_wells().len(), 1);
+
+    assert!(sim.try_selecting(80.4, 100.0));
+    sim.release_selection();
+    sim.try_removing(119.6, 100.0);
+    assert!(sim.gravity_wells().is_empty());
+}
+
+#[test]
+fn same_seed_gives_identical_particles() {
+    let mut a = Simulation::with_seed(800, 600, 1234);
+    let mut b = Simulation::with_seed(800, 600, 1234);
+    a.initialize_particles(100);
+    b.initialize_particles(100);
+
+    for (pa, pb) in a.particles().iter().zip(b.particles().iter()) {
+        assert_eq!(pa.pos, pb.pos);
+        assert_eq!(pa.vel, pb.vel);
+        assert_eq!(
+            (pa.color.r, pa.color.g, pa.color.b),
+            (pb.color.r, pb.color.g, pb.color.b)
+        );
+    }
+}
+
+#[test]
+fn reseed_restarts_the_sequence() {
+    let mut sim = Simulation::with_seed(800, 600, 42);
+    sim.initialize_particles(10);
+    let first: Vec<_> = sim.particles().iter().map(|p| p.pos).collect();
+
+    sim.clear_particles();
+    sim.reseed(42);
+    sim.initialize_particles(10);
+    let second: Vec<_> = sim.particles().iter().map(|p| p.pos).collect();
+
+    assert_eq!(first, second);
+    assert_eq!(sim.seed(), 42);
+}
+
+#[test]
+fn result_does_not_depend_on_how_time_is_split() {
+    let mut a = simulation_with_well(7);
+    let mut b = simulation_with_well(7);
+
+    a.step(0.21);
+    for _ in 0..7 {
+        b.step(0.03);
+    }
+
+    assert_eq!(a.tick_count(), 12);
+    assert_eq!(b.tick_count(), a.tick_count());
+    assert_eq!(b.particles().len(), a.particles().len());
+    for (pa, pb) in a.particles().iter().zip(b.particles().iter()) {
+        assert!((pa.pos[0] - pb.pos[0]).abs() < 1e-9);
+        assert!((pa.pos[1] - pb.pos[1]).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn resize_can_keep_or_rescale_positions() {
+    let mut sim = simulation_with_well(3);
+    let id = sim.add_emitter(EmitterShape::Line, 200.0, 150.0);
+    sim.emitter_mut(id).unwrap().size = [100.0, 50.0];
+    let before: Vec<_> = sim.particles().iter().map(|p| p.pos).collect();
+
+    // Only the bounds change
+    sim.resize(1600, 900, false);
+    assert_eq!((sim.width(), sim.height()), (1600, 900));
+    let after: Vec<_> = sim.particles().iter().map(|p| p.pos).collect();
+    assert_eq!(before, after);
+    assert_eq!(sim.gravity_wells()[0].pos, [400.0, 300.0]);
+
+    // Everything keeps its place relative to the edges
+    sim.resize(800, 300, true);
+    assert_eq!(sim.gravity_wells()[0].pos, [200.0, 100.0]);
+    assert_eq!(sim.emitters()[0].pos, [100.0, 50.0]);
+    assert_eq!(sim.emitters()[0].size, [50.0, 50.0 * (300.0 / 900.0)]);
+    for (before, after) in before.iter().zip(sim.particles().iter()) {
+        assert!((after.pos[0] - before[0] / 2.0).abs() < 1e-3);
+        assert!((after.pos[1] - before[1] / 3.0).abs() < 1e-3);
+    }
+}
+
+#[test]
+fn rescaling_keeps_undo_history_in_step() {
+    let mut sim = Simulation::with_seed(800, 600, 3);
+    sim.spawn_gravity_well(400.0, 300.0);
+    sim.spawn_gravity_well(100.0, 100.0);
+    assert!(sim.try_selecting(400.0, 300.0));
+    sim.move_selection_by(200.0, 150.0);
+    sim.release_selection();
+    sim.try_removing(100.0, 100.0);
+    assert!(sim.undo());
+
+    sim.resize(400, 200, true);
+    assert_eq!(sim.gravity_wells()[0].pos, [300.0, 150.0]);
+    // The removal that was undone comes back at the scaled position
+    assert!(sim.redo());
+    assert_eq!(sim.gravity_wells().len(), 1);
+    assert!(sim.undo());
+    assert_eq!(sim.gravity_wells()[1].pos, [50.0, 100.0 * (200.0 / 600.0)]);
+    // Undoing the move puts the well back where it started, at the new scale
+    assert!(sim.undo());
+    assert_eq!(sim.gravity_wells()[0].pos, [200.0, 100.0]);
+    // Undoing the adds removes the wells, nothing is left after that
+    assert!(sim.undo());
+    assert!(sim.undo());
+    assert!(sim.gravity_wells().is_empty());
+    assert!(!sim.can_undo());
+
+    // Resizing to the same size leaves everything alone, including the redo history
+    sim.resize(400, 200, true);
+    assert!(sim.can_redo());
+}
+
+#[test]
+fn borders_follow_the_new_size() {
+    let mut sim = Simulation::with_seed(800, 600, 1);
+    sim.set_border_mode(BorderMode::Despawn);
+    sim.spawn_particle(700.0, 100.0, 0.0, 0.0);
+    sim.spawn_particle(100.0, 100.0, 0.0, 0.0);
+    sim.resize(400, 300, false);
+    sim.step(0.1);
+    assert_eq!(sim.particles().len(), 1);
+    assert_eq!(sim.particles().pos_x()[0], 100.0);
+}
+
+#[test]
+fn pull_scales_with_well_mass_and_the_global_multiplier() {
+    let base = velocity_after_one_tick(300.0, 300.0, |_| {})[0];
+    assert!(base > 0.0);
+
+    let heavier = velocity_after_one_tick(300.0, 300.0, |sim| {
+        assert!(sim.set_well_mass(0, 180.0));
+    });
+    assert_near(heavier[0], base * 2.0);
+
+    let multiplied = velocity_after_one_tick(300.0, 300.0, |sim| {
+        sim.set_gravity_well_mass_multiplier(0.5);
+    });
+    assert_near(multiplied[0], base * 0.5);
+
+    let both = velocity_after_one_tick(300.0, 300.0, |sim| {
+        sim.set_well_mass(0, 180.0);
+        sim.set_gravity_well_mass_multiplier(3.0);
+    });
+    assert_near(both[0], base * 6.0);
+
+    let massless = velocity_after_one_tick(300.0, 300.0, |sim| {
+        sim.set_well_mass(0, 0.0);
+    });
+    assert_eq!(massless, [0.0, 0.0]);
+}
+
+#[test]
+fn well_kinds_push_in_different_directions() {
+    let with_kind = |kind: WellKind| {
+        velocity_after_one_tick(300.0, 300.0, |sim| {
+            // Swaps the attractor for a well of the given kind
+            sim.undo();
+            sim.spawn_gravity_well_of_kind(400.0, 300.0, kind);
+        })
+    };
+    let attracted = with_kind(WellKind::Attractor);
+    let repelled = with_kind(WellKind::Repulsor);
+    let swirled = with_kind(WellKind::Vortex);
+
+    // The particle is to the left of the well
+    assert!(attracted[0] > 0.0);
+    assert_eq!(attracted[1], 0.0);
+    assert_eq!(repelled, [-attracted[0], 0.0]);
+    // Vortices push at right angles to the well, with the same strength
+    assert_eq!(swirled[0], 0.0);
+    assert_near(swirled[1].abs(), attracted[0]);
+}
+
+#[test]
+fn long_steps_are_capped() {
+    let mut sim = simulation_with_well(5);
+    // A tab that was in the background for a minute only catches up a quarter second
+    sim.step(60.0);
+    let max_ticks = (MAX_STEP_SECONDS / sim.get_fixed_timestep()).round() as u64;
+    assert_eq!(sim.tick_count(), max_ticks);
+    // And the dropped time doesn't spill into the next step
+    sim.step(0.0);
+    assert_eq!(sim.tick_count(), max_ticks);
+
+    // Ticks longer than the cap still happen
+    let mut slow = Simulation::with_seed(800, 600, 1);
+    slow.set_fixed_timestep(1.0);
+    slow.step(2.5);
+    assert_eq!(slow.tick_count(), 1);
+}
+
+#[test]
+fn force_laws_fall_off_differently_with_distance() {
+    // Distances are in units of distance_scale (30 pixels)
+    let config = |law: ForceLaw| ForceConfig {
+        law,
+        exponent: 2.0,
+        ..ForceConfig::default()
+    };
+    let magnitude = |law: ForceLaw, d: f64| config(law).magnitude(10.0, d * 30.0);
+
+    // Flat inside one unit, then 1/d
+    assert_near(magnitude(ForceLaw::InverseLinear, 0.5), 10.0);
+    assert_near(magnitude(ForceLaw::InverseLinear, 4.0), 2.5);
+    // Softened so it goes to zero at the center, 1/d^2 far away
+    assert_eq!(magnitude(ForceLaw::InverseSquare, 0.0), 0.0);
+    assert_near(magnitude(ForceLaw::InverseSquare, 1.0), 10.0 / 8f64.sqrt());
+    assert_near(magnitude(ForceLaw::InverseSquare, 100.0), 10.0 / 10001f64.powf(1.5) * 100.0);
+    assert_near(magnitude(ForceLaw::Constant, 0.1), 10.0);
+    assert_near(magnitude(ForceLaw::Constant, 50.0), 10.0);
+    assert_near(magnitude(ForceLaw::Spring, 3.0), 30.0);
+    // 1/d^exponent, capped inside the softening distance
+    assert_near(magnitude(ForceLaw::Power, 0.5), 10.0);
+    assert_near(magnitude(ForceLaw::Power, 2.0), 2.5);
+}
+
+#[test]
+fn force_law_decides_how_distance_affects_the_pull() {
+    let pull = |law: ForceLaw, x: f64| {
+        velocity_after_one_tick(x, 300.0, |sim| {
+            sim.set_force_config(ForceConfig {
+                law,
+                ..ForceConfig::default()
+            })
+        })[0]
+    };
+    // Particles 60 and 240 pixels from the well
+    assert_near(pull(ForceLaw::Constant, 340.0), pull(ForceLaw::Constant, 160.0));
+    assert_near(pull(ForceLaw::InverseLinear, 340.0), 4.0 * pull(ForceLaw::InverseLinear, 160.0));
+    assert_near(pull(ForceLaw::Spring, 160.0), 4.0 * pull(ForceLaw::Spring, 340.0));
+}
+
+// Speed of a particle launched at 300 pixels per second after a second of drag alone
+fn speed_after_one_second(drag: DragConfig) -> f64 {
+    let mut sim = Simulation::with_seed(800, 600, 1);
+    sim.set_drag_config(drag);
+    sim.spawn_particle(0.0, 300.0, 300.0, 0.0);
+    for _ in 0..60 {
+        sim.tick(1.0 / 60.0);
+    }
+    let vel = sim.particles().vel(0);
+    assert_eq!(vel[1], 0.0);
+    vel[0]
+}
+
+#[test]
+fn linear_drag_decays_speed_exponentially() {
+    let drag = DragConfig::default();
+    let speed = speed_after_one_second(drag);
+    let expected = 300.0 * (-drag.linear_coefficient).exp();
+    assert!((speed / expected - 1.0).abs() < 0.01, "{} vs {}", speed, expected);
+    // Same as the old 0.99 per tick velocity scale
+    assert!((speed / (300.0 * 0.99f64.powi(60)) - 1.0).abs() < 0.01);
+}
+
+#[test]
+fn quadratic_drag_slows_fast_particles_more() {
+    let drag = DragConfig {
+        model: DragModel::Quadratic,
+        ..DragConfig::default()
+    };
+    // dv/dt = -k v^2 gives v = v0 / (1 + k v0 t)
+    let speed = speed_after_one_second(drag);
+    let expected = 300.0 / (1.0 + drag.quadratic_coefficient * 300.0);
+    assert!((speed / expected - 1.0).abs() < 0.01, "{} vs {}", speed, expected);
+    // Switching models doesn't carry the linear strength over,
+    // so particles keep a similar speed instead of stopping dead
+    assert!(speed > 100.0);
+
+    let mut switched = drag;
+    switched.set_coefficient(0.01);
+    assert_eq!(switched.quadratic_coefficient, 0.01);
+    assert_eq!(switched.linear_coefficient, DragConfig::default().linear_coefficient);
+    switched.model = DragModel::Linear;
+    assert_eq!(switched.coefficient(), DragConfig::default().linear_coefficient);
+}
+
+#[test]
+fn speed_limit_clamps_or_squashes() {
+    let speed_after_tick = |mode: SpeedLimitMode, vel_x: f64| {
+        let mut sim = Simulation::with_seed(800, 600, 1);
+        sim.set_drag_config(DragConfig {
+            model: DragModel::None,
+            ..DragConfig::default()
+        });
+        sim.set_speed_limit(SpeedLimit {
+            mode,
+            max_speed: 500.0,
+        });
+        sim.spawn_particle(400.0, 300.0, vel_x, 0.0);
+        sim.tick(1.0 / 60.0);
+        sim.particles().vel(0)[0]
+    };
+
+    assert_eq!(speed_after_tick(SpeedLimitMode::None, 2000.0), 2000.0);
+    // Clamping leaves slower particles alone and cuts faster ones to the limit
+    assert_eq!(speed_after_tick(SpeedLimitMode::Clamp, 300.0), 300.0);
+    assert_eq!(speed_after_tick(SpeedLimitMode::Clamp, 2000.0), 500.0);
+    // The soft limit slows everything a little, and never quite reaches the limit
+    let soft_slow = speed_after_tick(SpeedLimitMode::Soft, 300.0);
+    assert_near(soft_slow, 500.0 * 0.6f64.tanh());
+    assert!(soft_slow < 300.0);
+    let soft_fast = speed_after_tick(SpeedLimitMode::Soft, 2000.0);
+    assert!(soft_fast > 495.0 && soft_fast < 500.0, "{}", soft_fast);
+    // Direction is kept
+    assert_eq!(speed_after_tick(SpeedLimitMode::Clamp, -2000.0), -500.0);
+}
+
+#[test]
+fn particles_fade_out_and_expire_at_the_end_of_their_lifetime() {
+    let mut sim = Simulation::with_seed(800, 600, 1);
+    sim.set_particle_lifetime(1.0);
+    sim.spawn_particle(100.0, 100.0, 0.0, 0.0);
+    sim.set_particle_lifetime(0.0);
+    sim.spawn_particle(200.0, 100.0, 0.0, 0.0);
+    assert_eq!(sim.particles().get(0).lifetime, 1.0);
+    assert!(sim.particles().get(1).lifetime.is_infinite());
+
+    let trail_alphas = |sim: &Simulation| {
+        let mut vertices = vec![0.0; sim.particles().len() * 4];
+        let mut colors = vec![0; sim.particles().len() * 8];
+        particle_trail_lines(sim.particles(), 0.1, &mut vertices, &mut colors);
+        colors.chunks(8).map(|color| (color[3], color[7])).collect::<Vec<_>>()
+    };
+    assert_eq!(trail_alphas(&sim), vec![(255, 0), (255, 0)]);
+
+    // Half way through its life it is drawn at half opacity
+    for _ in 0..30 {
+        sim.tick(1.0 / 60.0);
+    }
+    assert!((sim.particles().get(0).opacity() - 0.5).abs() < 1e-3);
+    let alphas = trail_alphas(&sim);
+    assert!((126..=128).contains(&alphas[0].0), "{:?}", alphas);
+    assert_eq!(alphas[1], (255, 0));
+
+    // And once it is a second old it is removed, leaving the one that lives forever
+    for _ in 0..31 {
+        sim.tick(1.0 / 60.0);
+    }
+    assert_eq!(sim.particles().len(), 1);
+    assert_eq!(sim.particles().pos(0), [200.0, 100.0]);
+}
+
+#[test]
+fn emitters_carry_fractional_particles_over() {
+    let mut emitter = Emitter::new(0, EmitterShape::Point, [0.0, 0.0]);
+    emitter.rate = 90.0;
+    // One and a half particles per 60 Hz tick
+    let counts: Vec<u32> = (0..6).map(|_| emitter.advance(1.0 / 60.0)).collect();
+    assert_eq!(counts.iter().sum::<u32>(), 9);
+    assert!(counts.iter().all(|&count| count == 1 || count == 2), "{:?}", counts);
+
+    emitter.rate = -10.0;
+    assert_eq!(emitter.advance(1.0), 0);
+    // Rates that aren't finite spawn nothing rather than every particle they can
+    for &rate in &[f64::INFINITY, f64::NAN] {
+        emitter.rate = rate;
+        assert_eq!(emitter.advance(1.0 / 60.0), 0);
+    }
+    // And don't leave the accumulator broken once the rate is fixed
+    emitter.rate = 60.0;
+    assert_eq!(emitter.advance(1.0), 60);
+    emitter.rate = 1e12;
+    assert_eq!(emitter.advance(1.0), Emitter::MAX_RATE as u32);
+
+    let mut sim = Simulation::with_seed(800, 600, 1);
+    let id = sim.add_emitter(EmitterShape::Point, 400.0, 300.0);
+    sim.emitter_mut(id).unwrap().rate = 90.0;
+    for _ in 0..60 {
+        sim.tick(1.0 / 60.0);
+    }
+    assert_eq!(sim.particles().len(), 90);
+    assert!(sim.remove_emitter(id));
+    sim.tick(1.0 / 60.0);
+    assert_eq!(sim.particles().len(), 90);
+}
+
+#[test]
+fn emitters_spawn_on_their_shape_with_their_lifetime_and_palette() {
+    let mut rng = rand_pcg::Pcg32::seed_from_u64(3);
+    let palette = vec![Color::from_u32(0xff0000ff), Color::from_u32(0x00ff00ff)];
+    let emitted = |shape: EmitterShape, rng: &mut rand_pcg::Pcg32| {
+        let mut emitter = Emitter::new(0, shape, [100.0, 200.0]);
+        emitter.size = [40.0, 20.0];
+        emitter.lifetime = 2.5;
+        emitter.palette = palette.clone();
+        (0..200).map(|_| emitter.emit(rng)).collect::<Vec<_>>()
+    };
+
+    for particle in emitted(EmitterShape::Point, &mut rng) {
+        assert_eq!(particle.pos, [100.0, 200.0]);
+        assert_eq!(particle.lifetime, 2.5);
+        assert!(palette.iter().any(|c| c.to_u32() == particle.color.to_u32()));
+        // Speed is 100 +/- 50
+        let speed = (particle.vel[0].powi(2) + particle.vel[1].powi(2)).sqrt();
+        assert!((50.0..=150.0).contains(&speed), "{}", speed);
+    }
+    for particle in emitted(EmitterShape::Line, &mut rng) {
+        // Along the segment from pos to pos + size
+        let t = (particle.pos[0] - 100.0) / 40.0;
+        assert!((0.0..=1.0).contains(&t));
+        assert!((particle.pos[1] - (200.0 + 20.0 * t)).abs() < 1e-9);
+    }
+    for particle in emitted(EmitterShape::Circle, &mut rng) {
+        let distance = ((particle.pos[0] - 100.0).powi(2) + (particle.pos[1] - 200.0).powi(2)).sqrt();
+        assert!(distance <= 40.0);
+    }
+    for particle in emitted(EmitterShape::Rectangle, &mut rng) {
+        assert!((80.0..=120.0).contains(&particle.pos[0]));
+        assert!((190.0..=210.0).contains(&particle.pos[1]));
+    }
+}
+
+#[test]
+fn removed_particles_leave_the_rest_in_order_with_their_attributes() {
+    let mut sim = Simulation::with_seed(800, 600, 4);
+    sim.set_border_mode(BorderMode::Despawn);
+    for i in 0..6 {
+        sim.set_particle_lifetime(if i == 4 { 0.01 } else { 0.0 });
+        // Every other particle starts outside the area
+        let x = if i % 2 == 0 { 100.0 * i as f64 + 50.0 } else { -100.0 };
+        sim.spawn_particle(x, 50.0 * i as f64, 0.0, 0.0);
+    }
+    let before: Vec<_> = sim.particles().iter().collect();
+
+    sim.tick(1.0 / 60.0);
+    // The one at index 4 expired, the odd ones left
+    let particles = sim.particles();
+    assert_eq!(particles.len(), 2);
+    for (particle, original) in particles.iter().zip(&[before[0], before[2]]) {
+        assert_eq!(particle.pos, original.pos);
+        assert_eq!(particle.color.to_u32(), original.color.to_u32());
+        assert!(particle.lifetime.is_infinite());
+        assert!((particle.age - 1.0 / 60.0).abs() < 1e-6);
+    }
+    assert_eq!(particles.colors().len(), 2);
+    assert_eq!(particles.ages().len(), 2);
+}
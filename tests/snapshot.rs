@@ -0,0 +1,110 @@
+// Binary snapshots of the full simulation state
+
+mod common;
+
+use rust_webgl_particles_backend::simulation::Simulation;
+use rust_webgl_particles_backend::snapshot::{SnapshotError, SNAPSHOT_VERSION};
+use common::{assert_same_particles, busy_simulation};
+
+#[test]
+fn restored_snapshot_continues_identically() {
+    let mut original = busy_simulation();
+    let bytes = original.snapshot();
+
+    let mut restored = Simulation::with_seed(10, 10, 0);
+    restored.restore(&bytes).unwrap();
+    assert_same_particles(&original, &restored);
+    assert_eq!(restored.settings(), original.settings());
+    assert_eq!((restored.width(), restored.height()), (800, 600));
+
+    // Emitters draw from the random number generator, so this only matches
+    // if its state was carried over too
+    original.step(1.0);
+    restored.step(1.0);
+    assert_same_particles(&original, &restored);
+    assert_eq!(restored.snapshot(), original.snapshot());
+}
+
+#[test]
+fn snapshot_can_rewind() {
+    let mut sim = busy_simulation();
+    let checkpoint = sim.snapshot();
+    let before: Vec<f32> = sim.particles().pos_x().to_vec();
+    sim.step(2.0);
+    sim.restore(&checkpoint).unwrap();
+    assert_eq!(sim.particles().pos_x(), before);
+}
+
+#[test]
+fn snapshot_is_compact() {
+    let mut sim = Simulation::with_seed(800, 600, 3);
+    sim.initialize_particles(10_000);
+    // 28 bytes per particle plus a small fixed header
+    assert!(sim.snapshot().len() < 10_000 * 28 + 512);
+}
+
+#[test]
+fn bad_snapshots_are_rejected_without_changing_state() {
+    let mut sim = busy_simulation();
+    let good = sim.snapshot();
+    let particle_count = sim.particles().len();
+
+    let mut bad_magic = good.clone();
+    bad_magic[0] = b'X';
+    assert_eq!(sim.restore(&bad_magic), Err(SnapshotError::BadMagic));
+    assert_eq!(sim.restore(&[]), Err(SnapshotError::BadMagic));
+
+    let mut bad_version = good.clone();
+    bad_version[4..8].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+    assert_eq!(
+        sim.restore(&bad_version),
+        Err(SnapshotError::UnsupportedVersion(SNAPSHOT_VERSION + 1))
+    );
+
+    for len in &[8, 20, good.len() / 2, good.len() - 1] {
+        match sim.restore(&good[..*len]) {
+            Err(SnapshotError::Truncated(_)) => {}
+            other => panic!("expected a truncated error for {} bytes, got {:?}", len, other),
+        }
+    }
+
+    // PCG increments are always odd, it comes after the magic, version, size, seed and state
+    let mut even_increment = good.clone();
+    even_increment[32] &= !1;
+    assert_eq!(sim.restore(&even_increment), Err(SnapshotError::InvalidValue("rng increment")));
+
+    // A NaN time accumulator would stop the simulation from ever ticking again
+    let mut nan_accumulator = good.clone();
+    nan_accumulator[40..48].copy_from_slice(&f64::NAN.to_le_bytes());
+    assert_eq!(
+        sim.restore(&nan_accumulator),
+        Err(SnapshotError::InvalidValue("time accumulator"))
+    );
+
+    // And an infinite emitter rate would try to spawn forever
+    let rate = 45.0f64.to_le_bytes();
+    let rate_offset = good.windows(8).position(|bytes| bytes == rate).unwrap();
+    let mut infinite_rate = good.clone();
+    infinite_rate[rate_offset..rate_offset + 8].copy_from_slice(&f64::INFINITY.to_le_bytes());
+    assert_eq!(sim.restore(&infinite_rate), Err(SnapshotError::InvalidValue("emitter rate")));
+
+    let count_offset = good.len() - particle_count * 28 - 4;
+    let mut nan_position = good.clone();
+    nan_position[count_offset + 4..count_offset + 8].copy_from_slice(&f32::NAN.to_le_bytes());
+    assert_eq!(
+        sim.restore(&nan_position),
+        Err(SnapshotError::InvalidValue("particle x positions"))
+    );
+
+    let mut trailing = good.clone();
+    trailing.extend_from_slice(&[0, 0, 0]);
+    assert_eq!(sim.restore(&trailing), Err(SnapshotError::TrailingBytes(3)));
+
+    // A particle count far larger than the data that follows
+    let mut huge_count = good.clone();
+    huge_count[count_offset..count_offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(sim.restore(&huge_count), Err(SnapshotError::Truncated("particles")));
+
+    assert_eq!(sim.particles().len(), particle_count);
+    assert_eq!(sim.snapshot(), good);
+}
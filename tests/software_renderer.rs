@@ -0,0 +1,191 @@
+// Rendering frames without a GPU through the software backend
+
+use rust_webgl_particles_backend::gravity_well::WellKind;
+use rust_webgl_particles_backend::renderer::{RenderBackend, Renderer, TexturedQuad};
+use rust_webgl_particles_backend::simulation::Simulation;
+use rust_webgl_particles_backend::software_backend::{SoftwareBackend, Texture};
+use std::fs::File;
+use std::path::Path;
+
+const BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+// 2x2 texture with a different color in each corner
+fn corners_texture() -> Texture {
+    #[rustfmt::skip]
+    let pixels = vec![
+        255, 0, 0, 255,     0, 255, 0, 255,
+        0, 0, 255, 255,     255, 255, 255, 255,
+    ];
+    Texture::new(2, 2, pixels)
+}
+
+#[test]
+fn clear_fills_every_pixel() {
+    let mut backend = SoftwareBackend::new(5, 3);
+    assert!(backend.pixels().iter().all(|&c| c == 0));
+    backend.clear([1.0, 0.5, 0.0, 1.0]);
+    assert!(backend.pixels().chunks(4).all(|p| p == [255, 128, 0, 255]));
+}
+
+#[test]
+fn lines_blend_their_end_colors_and_alpha() {
+    let mut backend = SoftwareBackend::new(20, 5);
+    backend.clear(BACKGROUND);
+    // Opaque white fading to transparent, along the middle of row 2
+    backend.draw_lines(&[0.0, 2.5, 20.0, 2.5], &[255, 255, 255, 255, 255, 255, 255, 0]);
+
+    let brightness: Vec<u8> = (0..20).map(|x| backend.pixel(x, 2)[0]).collect();
+    assert!(brightness.windows(2).all(|pair| pair[0] >= pair[1]));
+    assert!(brightness[0] > 240);
+    assert!(brightness[19] < 15);
+    // Lined up with pixel centers, nothing spills into the rows around it
+    for x in 0..20 {
+        assert_eq!(backend.pixel(x, 1), [0, 0, 0, 255]);
+        assert_eq!(backend.pixel(x, 3), [0, 0, 0, 255]);
+    }
+}
+
+#[test]
+fn lines_are_anti_aliased() {
+    let mut backend = SoftwareBackend::new(10, 10);
+    backend.clear(BACKGROUND);
+    // Halfway between rows 4 and 5, each gets half the coverage
+    backend.draw_lines(&[0.0, 5.0, 10.0, 5.0], &[255, 255, 255, 255, 255, 255, 255, 255]);
+    let (above, below) = (backend.pixel(3, 4)[0], backend.pixel(3, 5)[0]);
+    assert!((127..=128).contains(&above));
+    assert_eq!(above, below);
+
+    // A steep line is stepped along y instead of x, every row gets covered
+    let mut backend = SoftwareBackend::new(10, 10);
+    backend.clear(BACKGROUND);
+    backend.draw_lines(&[2.5, 0.0, 4.5, 10.0], &[255, 255, 255, 255, 255, 255, 255, 255]);
+    for y in 0..10 {
+        let row_total: u32 = (0..10).map(|x| backend.pixel(x, y)[0] as u32).sum();
+        assert!((253..=257).contains(&row_total), "row {} sums to {}", y, row_total);
+    }
+}
+
+#[test]
+fn lines_off_the_frame_are_clipped() {
+    let mut backend = SoftwareBackend::new(4, 4);
+    backend.clear(BACKGROUND);
+    backend.draw_lines(
+        &[-100.0, -50.0, 200.0, 300.0, f32::NAN, 0.0, 3.0, 3.0],
+        &[255; 16],
+    );
+    assert!(backend.pixels().chunks(4).all(|p| p[3] == 255));
+}
+
+#[test]
+fn alpha_blends_over_what_is_already_drawn() {
+    let mut backend = SoftwareBackend::new(4, 4);
+    backend.clear([0.0, 0.0, 1.0, 1.0]);
+    backend.draw_lines(&[0.0, 1.5, 4.0, 1.5], &[255, 0, 0, 128, 255, 0, 0, 128]);
+    let [r, g, b, a] = backend.pixel(2, 1);
+    assert!((127..=129).contains(&r));
+    assert_eq!(g, 0);
+    assert!((126..=128).contains(&b));
+    // Alpha is blended with the same factors as color, like the WebGl blend function
+    assert_eq!(a, 191);
+}
+
+#[test]
+fn quads_are_textured_tinted_and_rotated() {
+    let quad = TexturedQuad {
+        center: [10.0, 10.0],
+        half_size: 8.0,
+        rotation_deg: 0.0,
+        tint: [1.0, 1.0, 1.0, 1.0],
+    };
+    let mut backend = SoftwareBackend::new(20, 20);
+    backend.add_texture("corners", corners_texture());
+    backend.clear(BACKGROUND);
+    backend.draw_textured_quads("corners", &[quad]);
+    assert_eq!(backend.pixel(3, 3), [255, 0, 0, 255]);
+    assert_eq!(backend.pixel(16, 3), [0, 255, 0, 255]);
+    assert_eq!(backend.pixel(3, 16), [0, 0, 255, 255]);
+    assert_eq!(backend.pixel(16, 16), [255, 255, 255, 255]);
+    // Outside the quad
+    assert_eq!(backend.pixel(1, 1), [0, 0, 0, 255]);
+
+    // A quarter turn clockwise moves the top left corner to the top right
+    backend.clear(BACKGROUND);
+    backend.draw_textured_quads(
+        "corners",
+        &[TexturedQuad {
+            rotation_deg: 90.0,
+            tint: [0.5, 1.0, 1.0, 1.0],
+            ..quad
+        }],
+    );
+    assert_eq!(backend.pixel(16, 3), [128, 0, 0, 255]);
+    assert_eq!(backend.pixel(3, 3), [0, 0, 255, 255]);
+
+    // Corners of a 45 degree turn stick out past the unrotated square
+    backend.clear(BACKGROUND);
+    backend.draw_textured_quads("corners", &[TexturedQuad { rotation_deg: 45.0, ..quad }]);
+    assert_ne!(backend.pixel(10, 0), [0, 0, 0, 255]);
+    assert_eq!(backend.pixel(3, 3), [0, 0, 0, 255]);
+
+    // Missing textures are drawn plain blue
+    backend.clear(BACKGROUND);
+    backend.draw_textured_quads("missing", &[quad]);
+    assert_eq!(backend.pixel(10, 10), [0, 0, 255, 255]);
+}
+
+fn render_scene() -> SoftwareBackend {
+    let mut sim = Simulation::with_seed(128, 96, 9);
+    sim.spawn_gravity_well(90.0, 50.0);
+    sim.spawn_gravity_well_of_kind(25.0, 25.0, WellKind::Vortex);
+    sim.try_selecting(25.0, 25.0);
+    sim.set_particle_lifetime(2.0);
+    sim.initialize_particles(60);
+    // Half a second, in steps short enough not to be capped
+    sim.step(0.25);
+    sim.step(0.25);
+
+    let mut backend = SoftwareBackend::new(128, 96);
+    backend.add_texture("gravity_well", corners_texture());
+    let mut renderer = Renderer::new(backend);
+    renderer.clear_screen();
+    renderer.render_particles(sim.particles(), 0.1);
+    renderer.render_gravity_wells(sim.gravity_wells());
+    renderer.backend
+}
+
+fn read_png(path: &Path) -> (u32, u32, Vec<u8>) {
+    let decoder = png::Decoder::new(File::open(path).expect("missing golden image"));
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!(info.color_type, png::ColorType::Rgba);
+    pixels.truncate(info.buffer_size());
+    (info.width, info.height, pixels)
+}
+
+fn write_png(path: &Path, backend: &SoftwareBackend) {
+    let mut encoder = png::Encoder::new(File::create(path).unwrap(), backend.width(), backend.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header().unwrap().write_image_data(backend.pixels()).unwrap();
+}
+
+// Run with UPDATE_GOLDEN=1 to rewrite the golden image after an intended rendering change
+#[test]
+fn scene_matches_golden_image() {
+    let backend = render_scene();
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/software_scene.png");
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        write_png(&path, &backend);
+    }
+
+    let (width, height, golden) = read_png(&path);
+    assert_eq!((width, height), (backend.width(), backend.height()));
+    // Allow for float rounding differing slightly between platforms
+    let mismatched = golden
+        .iter()
+        .zip(backend.pixels())
+        .filter(|(a, b)| (**a as i32 - **b as i32).abs() > 2)
+        .count();
+    assert_eq!(mismatched, 0, "{} channels differ from the golden image", mismatched);
+}
@@ -0,0 +1,117 @@
+// Checks SpatialGrid queries against a naive scan over every particle
+
+mod common;
+
+use rust_webgl_particles_backend::collision::{self, CollisionConfig};
+use rust_webgl_particles_backend::color::Color;
+use rust_webgl_particles_backend::particle::{Particle, Particles};
+use rust_webgl_particles_backend::simulation::Simulation;
+use rust_webgl_particles_backend::spatial_grid::SpatialGrid;
+use common::random_positions;
+
+fn naive_within(positions: &[[f64; 2]], center: [f64; 2], radius: f64) -> Vec<usize> {
+    (0..positions.len())
+        .filter(|&i| vecmath::vec2_square_len(vecmath::vec2_sub(positions[i], center)) <= radius * radius)
+        .collect()
+}
+
+#[test]
+fn query_radius_matches_naive_scan() {
+    let positions = random_positions(11, 2000, [-50.0, -50.0], [350.0, 250.0]);
+    let mut grid = SpatialGrid::new(8.0);
+    grid.rebuild(positions.iter().copied());
+
+    for &center in &[[0.0, 0.0], [120.0, 80.0], [-500.0, 20.0], [349.0, 249.0]] {
+        for &radius in &[1.0, 8.0, 25.0] {
+            let mut found = grid.query_radius(center, radius);
+            found.sort_unstable();
+            assert_eq!(found, naive_within(&positions, center, radius));
+        }
+    }
+}
+
+#[test]
+fn pairs_match_naive_scan() {
+    let positions = random_positions(11, 500, [-50.0, -50.0], [350.0, 250.0]);
+    let mut grid = SpatialGrid::new(10.0);
+    grid.rebuild(positions.iter().copied());
+
+    let mut pairs = Vec::new();
+    grid.for_each_pair_within(10.0, |i, j| pairs.push((i, j)));
+    pairs.sort_unstable();
+
+    let mut expected = Vec::new();
+    for i in 0..positions.len() {
+        for j in naive_within(&positions, positions[i], 10.0) {
+            if i < j {
+                expected.push((i, j));
+            }
+        }
+    }
+    assert_eq!(pairs, expected);
+}
+
+#[test]
+fn empty_grid_finds_nothing() {
+    let mut grid = SpatialGrid::new(5.0);
+    grid.rebuild(std::iter::empty());
+    assert!(grid.query_radius([0.0, 0.0], 100.0).is_empty());
+}
+
+#[test]
+fn head_on_collision_swaps_velocities() {
+    let color = Color::from_u32(0xffffffff);
+    let mut particles = Particles::new();
+    particles.push(&Particle::new(0.0, 0.0, 10.0, 0.0, color));
+    particles.push(&Particle::new(3.0, 0.0, -10.0, 0.0, color));
+    let config = CollisionConfig {
+        collisions_enabled: true,
+        radius: 2.0,
+        ..CollisionConfig::default()
+    };
+    let mut grid = SpatialGrid::new(config.cell_size());
+    grid.rebuild(particles.positions());
+
+    collision::resolve_collisions(&mut particles, &grid, &config);
+
+    assert_eq!(particles.vel(0), [-10.0, 0.0]);
+    assert_eq!(particles.vel(1), [10.0, 0.0]);
+    let distance = vecmath::vec2_len(vecmath::vec2_sub(particles.pos(1), particles.pos(0)));
+    assert!((distance - 4.0).abs() < 1e-9);
+}
+
+#[test]
+fn pairs_pushed_apart_earlier_in_the_pass_are_left_alone() {
+    // 0 and 1 overlap the most, pushing them apart takes 0 out of reach of 2
+    let color = Color::from_u32(0xffffffff);
+    let mut particles = Particles::new();
+    particles.push(&Particle::new(0.0, 0.0, 0.0, 0.0, color));
+    particles.push(&Particle::new(1.0, 0.0, 0.0, 0.0, color));
+    particles.push(&Particle::new(3.9, 0.0, -10.0, 0.0, color));
+    let config = CollisionConfig {
+        collisions_enabled: true,
+        radius: 2.0,
+        ..CollisionConfig::default()
+    };
+    let mut grid = SpatialGrid::new(config.cell_size());
+    grid.rebuild(particles.positions());
+
+    collision::resolve_collisions(&mut particles, &grid, &config);
+
+    // 0 isn't pulled back towards 2 or handed its velocity
+    assert!((particles.pos(0)[0] + 1.5).abs() < 1e-9);
+    assert_eq!(particles.vel(0), [0.0, 0.0]);
+    // 2 only bounces off 1, the particle it's still touching
+    assert_eq!(particles.vel(1), [-10.0, 0.0]);
+    assert_eq!(particles.vel(2), [0.0, 0.0]);
+    assert!(particles.pos(2)[0] > 3.9);
+}
+
+#[test]
+fn simulation_point_query_finds_nearby_particles() {
+    let mut sim = Simulation::with_seed(800, 600, 5);
+    sim.spawn_particle(100.0, 100.0, 0.0, 0.0);
+    sim.spawn_particle(105.0, 100.0, 0.0, 0.0);
+    sim.spawn_particle(300.0, 100.0, 0.0, 0.0);
+    assert_eq!(sim.particles_within(100.0, 100.0, 10.0), vec![0, 1]);
+}
@@ -1,6 +1,6 @@
 "use strict";
 
-import { WasmApp } from "rust-webgl-particles-backend";
+import { WasmApp, WellKind, BorderMode } from "rust-webgl-particles-backend";
 import { FramerateDisplay } from "./framerate_display.js";
 
 // Globals for mouse position
@@ -35,12 +35,20 @@ canvas.style.touchAction = "none";
 // Set up UI with helper functions
 addEventCallbacksToCanvas(canvas);
 connectUICallbacks();
+addUndoShortcuts();
 
 // Here is our wasm backend instance that handles
 // the actual particle simulation
 const wasmApp = WasmApp.new();
 wasmApp.connect_canvas_element(canvas);
 
+// Keep the canvas sized to the page and sharp on high density displays,
+// moving everything along with the edges when it changes size
+wasmApp.set_rescale_on_resize(true);
+fitCanvasToPage();
+window.addEventListener("resize", fitCanvasToPage);
+watchPixelRatio();
+
 // Load the gravity well image and store it as a webGl texture
 // in the wasm app
 {
@@ -52,7 +60,7 @@ wasmApp.connect_canvas_element(canvas);
 }
 
 // Initialize canvas with one gravity well in the center, and some particles
-wasmApp.spawn_gravity_well(canvas.width / 2.0, canvas.height / 2.0);
+wasmApp.spawn_gravity_well(canvas.clientWidth / 2.0, canvas.clientHeight / 2.0);
 wasmApp.initialize_particles(3000);
 
 // Keeps track of recent fps measurements and updates the fps label
@@ -120,6 +128,26 @@ const animationFrameLoop = (currentFrameTime) => {
 requestAnimationFrame(animationFrameLoop);
 
 
+// Sizes the canvas to fill the width of its column at a 4:3 aspect ratio.
+// The simulation works in CSS pixels, the canvas itself gets
+// devicePixelRatio times as many pixels so nothing looks blurry
+function fitCanvasToPage() {
+	const width = canvas.parentElement.clientWidth;
+	const height = Math.round(width * 3 / 4);
+	canvas.style.width = `${width}px`;
+	canvas.style.height = `${height}px`;
+	wasmApp.resize(width, height, window.devicePixelRatio);
+}
+
+// devicePixelRatio changes when zooming or moving the window to another display
+function watchPixelRatio() {
+	const query = window.matchMedia(`(resolution: ${window.devicePixelRatio}dppx)`);
+	query.addEventListener("change", () => {
+		fitCanvasToPage();
+		watchPixelRatio();
+	}, { once: true });
+}
+
 // Functions related to the UI, including callbacks for all html input elements
 function updateParticleCountLabel() {
 	document.getElementById("particle-count-label").textContent =
@@ -133,9 +161,9 @@ function updateSimSpeedLabel() {
 }
 
 function connectUICallbacks() {
-	// Gravity Well Mass Slider
+	// Gravity Well Mass Multiplier Slider
 	document.getElementById("gravity-well-mass-slider").onchange = function() {
-		wasmApp.set_gravity_well_mass(this.value);
+		wasmApp.set_gravity_well_mass_multiplier(this.value);
 	}
 
 	// Clear Particles Button
@@ -143,9 +171,19 @@ function connectUICallbacks() {
 		wasmApp.clear_particles();
 	}
 
-	// Borders Active Checkbox
-	document.getElementById("borders-active-checkbox").onclick = function() {
-		wasmApp.set_borders_active(this.checked);
+	// Reset View Button
+	document.getElementById("reset-view-button").onclick = function() {
+		wasmApp.reset_camera();
+	}
+
+	// Border Mode Select
+	document.getElementById("border-mode-select").onchange = function() {
+		wasmApp.set_border_mode(BorderMode[this.value]);
+	}
+
+	// Border Restitution Slider
+	document.getElementById("border-restitution-slider").onchange = function() {
+		wasmApp.set_border_restitution(this.value);
 	}
 
 	// Sim Speed Down Button
@@ -167,10 +205,79 @@ function connectUICallbacks() {
 		wasmApp.set_particle_trail_scale(this.value);
 	}
 
+	// Particle Lifetime Slider
+	document.getElementById("particle-lifetime-slider").onchange = function() {
+		wasmApp.set_particle_lifetime(this.value);
+	}
+
 	// Remove Some Particles Button
 	document.getElementById("remove-some-particles-button").onclick = function() {
 		wasmApp.remove_particles(250);
 	}
+
+	// Save Scene Button, downloads the current scene as a JSON file
+	document.getElementById("export-scene-button").onclick = function() {
+		const blob = new Blob([wasmApp.export_scene()], { type: "application/json" });
+		const link = document.createElement("a");
+		link.href = URL.createObjectURL(blob);
+		link.download = "particles-scene.json";
+		link.click();
+		URL.revokeObjectURL(link.href);
+	}
+
+	// Load Scene Input, replaces the current scene with the chosen file
+	document.getElementById("import-scene-input").onchange = function() {
+		const file = this.files[0];
+		this.value = "";
+		if (!file) {
+			return;
+		}
+		file.text().then((json) => {
+			try {
+				wasmApp.import_scene(json);
+			} catch (error) {
+				alert(error);
+			}
+		});
+	}
+
+	// Recording Button, toggles recording and downloads the recording when stopped
+	document.getElementById("record-button").onclick = function() {
+		if (wasmApp.is_recording()) {
+			const blob = new Blob([wasmApp.stop_recording()], { type: "application/json" });
+			const link = document.createElement("a");
+			link.href = URL.createObjectURL(blob);
+			link.download = "particles-recording.json";
+			link.click();
+			URL.revokeObjectURL(link.href);
+			this.textContent = "Start Recording";
+		} else {
+			try {
+				wasmApp.start_recording();
+				this.textContent = "Stop Recording";
+			} catch (error) {
+				alert(error);
+			}
+		}
+	}
+
+	// Replay Recording Input, plays back the chosen recording from its start
+	document.getElementById("replay-input").onchange = function() {
+		const file = this.files[0];
+		this.value = "";
+		if (!file) {
+			return;
+		}
+		file.text().then((json) => {
+			try {
+				wasmApp.replay(json);
+				// Replaying drops any recording in progress
+				document.getElementById("record-button").textContent = "Start Recording";
+			} catch (error) {
+				alert(error);
+			}
+		});
+	}
 }
 
 // Set up mouse interaction through canvas events
@@ -183,6 +290,10 @@ function addEventCallbacksToCanvas(canvas) {
 		if (e.button === 0) {
 			if (e.ctrlKey) {
 				wasmApp.spawn_gravity_well(e.offsetX, e.offsetY);
+			} else if (e.shiftKey) {
+				wasmApp.spawn_gravity_well_of_kind(e.offsetX, e.offsetY, WellKind.Repulsor);
+			} else if (e.altKey) {
+				wasmApp.spawn_gravity_well_of_kind(e.offsetX, e.offsetY, WellKind.Vortex);
 			} else {
 				if (wasmApp.try_selecting(e.offsetX, e.offsetY)) {
 					isDragging = true;
@@ -190,6 +301,9 @@ function addEventCallbacksToCanvas(canvas) {
 					isSpawningParticles = true;
 				}
 			}
+		} else if (e.button === 1) {
+			// Keeps the browser from starting to autoscroll while panning
+			e.preventDefault();
 		} else if (e.button === 2) {
 			wasmApp.try_removing(e.offsetX, e.offsetY);
 		}
@@ -206,7 +320,10 @@ function addEventCallbacksToCanvas(canvas) {
 		let movementY = e.offsetY - mouseY;
 		mouseX = e.offsetX;
 		mouseY = e.offsetY;
-		if (isDragging) {
+		// Dragging with the middle button pans the view
+		if (e.buttons & 4) {
+			wasmApp.pan_by(movementX, movementY);
+		} else if (isDragging) {
 			wasmApp.move_selection_by(movementX, movementY);
 		} else {
 			if (!wasmApp.try_selecting(mouseX, mouseY)) {
@@ -215,6 +332,12 @@ function addEventCallbacksToCanvas(canvas) {
 		}
 	});
 
+	// Scrolling zooms in and out around the mouse
+	canvas.addEventListener("wheel", (e) => {
+		e.preventDefault();
+		wasmApp.zoom_at(e.offsetX, e.offsetY, Math.exp(-e.deltaY * 0.001));
+	}, { passive: false });
+
 	canvas.addEventListener("pointerup", (e) => {
 		if (e.button === 0) {
 			isSpawningParticles = false;
@@ -226,6 +349,23 @@ function addEventCallbacksToCanvas(canvas) {
 	});
 }
 
+// Ctrl+Z undoes the last gravity well edit, Ctrl+Shift+Z or Ctrl+Y redoes it
+function addUndoShortcuts() {
+	document.addEventListener("keydown", (e) => {
+		if (!(e.ctrlKey || e.metaKey)) {
+			return;
+		}
+		const key = e.key.toLowerCase();
+		if (key === "z" && !e.shiftKey) {
+			wasmApp.undo();
+			e.preventDefault();
+		} else if ((key === "z" && e.shiftKey) || key === "y") {
+			wasmApp.redo();
+			e.preventDefault();
+		}
+	});
+}
+
 // Helper function that randomizes spawned particle's starting
 // offset and velocity
 function spawnParticle() {